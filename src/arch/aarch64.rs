@@ -1,6 +1,7 @@
 use icicle_mem::perm;
 
-use crate::vm::{IcicleHelper, Return};
+use crate::syscall::{self, SyscallAbi};
+use crate::vm::{Boot, IcicleHelper, Return, TlsVariant};
 
 use std::os::unix::prelude::OsStrExt;
 use std::path::Path;
@@ -11,6 +12,44 @@ use pcode::VarNode;
 
 use crate::vm::{Param, Vm};
 
+/// the syscalls musl may use, with the number of params
+const SYSCALLS: &[(u64, &str, usize)] = &[
+    (25, "fcntl", 3),
+    (29, "ioctl", 3),
+    (56, "openat", 4),
+    (57, "close", 1),
+    (62, "lseek", 3),
+    (63, "read", 3),
+    (64, "write", 3),
+    (65, "readv", 3),
+    (66, "writev", 3),
+    (80, "fstat", 2),
+    (93, "exit", 1),
+    (94, "exit_group", 1),
+    (96, "set_tid_address", 1),
+    (113, "clock_gettime", 2),
+    (129, "kill", 2),
+    (134, "rt_sigaction", 4),
+    (135, "rt_sigprocmask", 4),
+    (160, "uname", 1),
+    (163, "getrlimit", 2),
+    (169, "gettimeofday", 2),
+    (172, "getpid", 0),
+    (173, "getppid", 0),
+    (174, "getuid", 0),
+    (175, "geteuid", 0),
+    (176, "getgid", 0),
+    (177, "getegid", 0),
+    (178, "gettid", 0),
+    (214, "brk", 1),
+    (215, "munmap", 2),
+    (216, "mremap", 5),
+    (222, "mmap", 6),
+    (226, "mprotect", 3),
+    (261, "prlimit64", 4),
+    (278, "getrandom", 3),
+];
+
 pub struct Aarch64 {
     pub helper: IcicleHelper,
     x: [VarNode; 31],
@@ -20,13 +59,27 @@ pub struct Aarch64 {
 }
 
 impl Aarch64 {
-    pub fn new(triple: &str, musl: &Path) -> Result<Self> {
+    pub fn new(triple: &str, musl: &Path, boot: Option<&Boot>) -> Result<Self> {
         let mut vm = icicle_vm::build(&icicle_vm::cpu::Config {
             triple: triple.parse().unwrap(),
             enable_shadow_stack: false,
             ..icicle_vm::cpu::Config::default()
         })?;
-        vm.env = icicle_vm::env::build_auto(&mut vm)?;
+        let reg = |name: &str| vm.cpu.arch.sleigh.get_reg(name).unwrap().var;
+        let syscall_abi = SyscallAbi {
+            nr: reg("x8"),
+            args: [
+                reg("x0"),
+                reg("x1"),
+                reg("x2"),
+                reg("x3"),
+                reg("x4"),
+                reg("x5"),
+            ],
+            ret: reg("x0"),
+            table: SYSCALLS,
+        };
+        let kernel = syscall::intercept(&mut vm, syscall_abi)?;
         vm.env
             .load(&mut vm.cpu, musl.as_os_str().as_bytes())
             .map_err(|e| anyhow!(e))?;
@@ -68,24 +121,35 @@ impl Aarch64 {
             .try_into()
             .unwrap();
         let sp = vm.cpu.arch.sleigh.get_reg("sp").unwrap().var;
-        let helper = IcicleHelper::new(
+        let tpidr_el0 = vm.cpu.arch.sleigh.get_reg("tpidr_el0").unwrap().var;
+        let mut helper = IcicleHelper::new(
             vm,
+            musl,
             0x1000_0000,
             0x1000_0000,
             0x2000_0000,
             0x1000_0000,
+            kernel,
         );
-        Ok(Self {
+        helper.init_tls(tpidr_el0, TlsVariant::AboveTp { gap: 16 })?;
+        helper.take_snapshot();
+        let mut vm = Self {
             helper,
             x,
             d,
             s,
             sp,
-        })
+        };
+        // optionally initialize musl, like the start of a process
+        if let Some(boot) = boot {
+            vm.boot(boot)?;
+        }
+        Ok(vm)
     }
 
     fn stack_used(params: &[Param]) -> u64 {
-        if params.len() > 7 {
+        let floats = params.iter().filter(|param| param.is_float()).count();
+        if params.len() - floats > 8 || floats > 8 {
             todo!();
         }
         0
@@ -102,14 +166,14 @@ impl Aarch64 {
 
         let stack_pos = self.helper.stack_addr + self.helper.stack_size;
 
-        if params.len() > 7 {
-            todo!()
-        }
-        for (i, param) in params.iter_mut().enumerate() {
-            match param {
-                Param::Usize(value) => {
-                    self.helper.icicle.cpu.write_reg(self.x[i], *value)
-                }
+        // the ints and the floats take the next free register of their own
+        // class, `f(int, double, int)` is x0, d0 and x1, also for the
+        // variadic functions
+        let mut x = self.x.into_iter().take(8);
+        let mut v = self.d.into_iter().zip(self.s);
+        for param in params.iter_mut() {
+            let (reg, value) = match param {
+                Param::Usize(value) => (x.next(), *value),
                 Param::HeapData(data) => {
                     let addr = self.helper.malloc(data.len() as u64)?;
                     // write the heap
@@ -119,25 +183,22 @@ impl Aarch64 {
                         perm::NONE,
                     )?;
                     // put the addr to the reg
-                    self.helper.icicle.cpu.write_reg(self.x[i], addr)
+                    (x.next(), addr)
                 }
                 Param::HeapFn(write_data) => {
-                    let addr = write_data(&mut self.helper)?;
                     // put the addr to the reg
-                    self.helper.icicle.cpu.write_reg(self.x[i], addr)
+                    (x.next(), write_data(&mut self.helper)?)
                 }
-                Param::F32(value) => self
-                    .helper
-                    .icicle
-                    .cpu
-                    .write_reg(self.s[i], value.to_bits() as u64),
-                Param::F64(value) => {
-                    self.helper.icicle.cpu.write_reg(self.d[i], value.to_bits())
+                Param::F32(value) => {
+                    (v.next().map(|(_, s)| s), value.to_bits() as u64)
                 }
-                Param::I64(value) => {
-                    self.helper.icicle.cpu.write_reg(self.x[i], *value as u64)
+                Param::F64(value) => {
+                    (v.next().map(|(d, _)| d), value.to_bits())
                 }
-            }
+                Param::I64(value) => (x.next(), *value as u64),
+            };
+            // `stack_used` already checked that all of them fit
+            self.helper.icicle.cpu.write_reg(reg.unwrap(), value);
         }
 
         // write the return addr to x30/LR
@@ -193,8 +254,9 @@ impl Vm for Aarch64 {
         params: &mut [Param],
         results: &mut [Return],
     ) -> Result<()> {
-        //clean the heap
-        self.helper.free_all();
+        // return to the state right after the library was loaded, unless
+        // inside a session
+        self.helper.prepare_call();
 
         let stack_len = Self::stack_used(params);
         self.helper.set_stack_len(stack_len)?;
@@ -205,7 +267,7 @@ impl Vm for Aarch64 {
         // set the function addr to pc
         self.helper.icicle.cpu.write_pc(function_addr);
 
-        let vm_exit = self.helper.icicle.run_until(return_addr);
+        let vm_exit = self.helper.run_until(return_addr);
         if vm_exit != icicle_vm::VmExit::Breakpoint {
             bail!(
                 "Vm exited at 0x{:016x} with {:?}",
@@ -215,7 +277,7 @@ impl Vm for Aarch64 {
         }
 
         self.get_results(results)?;
-        self.helper.icicle.cpu.reset();
+        self.helper.stats.results.extend_from_slice(results);
         Ok(())
     }
 }
@@ -0,0 +1,150 @@
+//! Find the arch and ABI of a binary from the ELF header, and build the
+//! backend for it.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::elf::{self, Elf};
+use crate::vm::{Boot, Vm};
+
+use super::{aarch64, x86, x86_64};
+
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+const EF_MIPS_ABI2: u32 = 0x20;
+const EF_MIPS_ABI: u32 = 0xf000;
+const EF_MIPS_ABI_O32: u32 = 0x1000;
+const EF_PPC64_ABI: u32 = 0x3;
+const EF_RISCV_FLOAT_ABI: u32 = 0x6;
+
+/// the float and calling convention of the arches that have more then one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Abi {
+    Default,
+    /// ARM with the float params in the vfp registers
+    ArmHardFloat,
+    ArmSoftFloat,
+    MipsO32,
+    MipsN32,
+    MipsN64,
+    /// PPC64 with function descriptors
+    Ppc64ElfV1,
+    Ppc64ElfV2,
+    RiscvSoftFloat,
+    RiscvSingleFloat,
+    RiscvDoubleFloat,
+}
+
+/// what the binary was compiled for
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub machine: u16,
+    pub class64: bool,
+    pub big_endian: bool,
+    pub abi: Abi,
+    /// the name musl uses for the arch, eg: `armhf`, it also tells if it's
+    /// soft float, like `mips-sf`
+    pub musl_name: Option<String>,
+    /// the x86 code is for i486 or i586, the ELF header is the same as for
+    /// i686, so it's never set by [`Target::from_elf`], the caller must tell
+    pub pre_i686: bool,
+}
+
+impl Target {
+    pub fn from_elf(elf: &Elf) -> Result<Self> {
+        let abi = match elf.machine {
+            elf::EM_ARM if elf.flags & EF_ARM_ABI_FLOAT_HARD != 0 => {
+                Abi::ArmHardFloat
+            }
+            elf::EM_ARM => Abi::ArmSoftFloat,
+            elf::EM_MIPS if elf.class64 => Abi::MipsN64,
+            elf::EM_MIPS if elf.flags & EF_MIPS_ABI2 != 0 => Abi::MipsN32,
+            elf::EM_MIPS if elf.flags & EF_MIPS_ABI == EF_MIPS_ABI_O32 => {
+                Abi::MipsO32
+            }
+            elf::EM_MIPS => bail!("Unknown MIPS ABI, flags 0x{:x}", elf.flags),
+            // ELFv1 binaries may not set the flag
+            elf::EM_PPC64 if elf.flags & EF_PPC64_ABI == 2 => Abi::Ppc64ElfV2,
+            elf::EM_PPC64 => Abi::Ppc64ElfV1,
+            elf::EM_RISCV => match (elf.flags & EF_RISCV_FLOAT_ABI) >> 1 {
+                0 => Abi::RiscvSoftFloat,
+                1 => Abi::RiscvSingleFloat,
+                2 => Abi::RiscvDoubleFloat,
+                _ => bail!("Unknown RISC-V ABI, flags 0x{:x}", elf.flags),
+            },
+            _ => Abi::Default,
+        };
+        Ok(Self {
+            machine: elf.machine,
+            class64: elf.class64,
+            big_endian: elf.big_endian,
+            abi,
+            musl_name: elf.musl_name().map(str::to_string),
+            pre_i686: false,
+        })
+    }
+
+    fn soft_float(&self) -> bool {
+        self.musl_name
+            .as_ref()
+            .is_some_and(|name| name.ends_with("-sf"))
+    }
+
+    /// the triple used by icicle
+    pub fn triple(&self) -> Result<String> {
+        let be = self.big_endian;
+        let arch = match (self.machine, self.class64) {
+            //NOTE there is no i486 triple, i586 is the closest one
+            (elf::EM_386, false) if self.pre_i686 => "i586",
+            (elf::EM_386, false) => "i686",
+            (elf::EM_X86_64, true) => "x86_64",
+            (elf::EM_AARCH64, true) if be => "aarch64_be",
+            (elf::EM_AARCH64, true) => "aarch64",
+            (elf::EM_ARM, false) if be => "armeb",
+            (elf::EM_ARM, false) => "arm",
+            (elf::EM_MIPS, false) if be => "mips",
+            (elf::EM_MIPS, false) => "mipsel",
+            (elf::EM_MIPS, true) if be => "mips64",
+            (elf::EM_MIPS, true) => "mips64el",
+            (elf::EM_PPC, false) => "powerpc",
+            (elf::EM_PPC64, true) if be => "powerpc64",
+            (elf::EM_PPC64, true) => "powerpc64le",
+            (elf::EM_RISCV, false) => "riscv32",
+            (elf::EM_RISCV, true) => "riscv64",
+            (machine, class64) => bail!(
+                "Unknown ELF machine {} {}",
+                machine,
+                if class64 { "64bits" } else { "32bits" }
+            ),
+        };
+        let env = match self.abi {
+            Abi::ArmHardFloat => "musleabihf",
+            Abi::ArmSoftFloat => "musleabi",
+            _ if self.soft_float() => "muslsf",
+            _ => "musl",
+        };
+        Ok(format!("{}-linux-{}", arch, env))
+    }
+}
+
+/// the backend for the binary, None if there is none for the arch, new
+/// backends need to be added here. `pre_i686` is the [`Target::pre_i686`] of
+/// the binary
+pub fn build(
+    musl: &Path,
+    pre_i686: bool,
+    boot: Option<&Boot>,
+) -> Result<Option<Box<dyn Vm>>> {
+    let mut target = Target::from_elf(&Elf::read(musl)?)?;
+    target.pre_i686 = pre_i686;
+    let triple = target.triple()?;
+    let vm: Box<dyn Vm> = match (target.machine, target.big_endian) {
+        (elf::EM_386, false) => Box::new(x86::X86::new(&triple, musl, boot)?),
+        (elf::EM_X86_64, false) => Box::new(x86_64::X86_64::new(musl, boot)?),
+        (elf::EM_AARCH64, false) => {
+            Box::new(aarch64::Aarch64::new(&triple, musl, boot)?)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(vm))
+}
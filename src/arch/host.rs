@@ -0,0 +1,166 @@
+//! Call the functions natively, using the host libc, as a reference for the
+//! emulated results.
+//!
+//! If the host is not musl, the results of some functions are expected to
+//! diverge, those are listed in [`GLIBC_DIVERGENCES`].
+//!
+//! The host can't implement [`crate::vm::Vm`], there is no icicle vm to give
+//! access to, so it only has the calls the comparison needs.
+
+use std::ffi::{c_char, CStr, CString};
+use std::mem::transmute;
+
+use anyhow::{bail, Result};
+
+use crate::report::FloatCheck;
+use crate::vm::{Input, Return};
+
+/// functions where glibc legitimately returns something different from musl
+pub const GLIBC_DIVERGENCES: &[(&str, &str)] = &[
+    ("rand", "glibc don't use the musl LCG"),
+    ("srand", "glibc don't use the musl LCG"),
+    ("log", "glibc sets errno, musl only raises fenv exceptions"),
+    ("sqrt", "glibc sets errno, musl only raises fenv exceptions"),
+    ("sysconf", "glibc read the limits from the system"),
+    ("getenv", "the host environment is not the boot one"),
+    ("setjmp", "can't return into a frame that was already left"),
+    ("longjmp", "can't return into a frame that was already left"),
+];
+
+/// true if the function is expected to diverge from musl on this host
+pub fn diverges(fn_sym: &str) -> bool {
+    !cfg!(target_env = "musl")
+        && GLIBC_DIVERGENCES.iter().any(|(name, _)| *name == fn_sym)
+}
+
+/// how the host results are compared, glibc and musl libm are not correctly
+/// rounded, so `sin` and `cos` may differ in the last ULP. The NaNs made by
+/// the host cpu may not have the sign of the emulated ones
+pub fn float_check(fn_sym: &str) -> FloatCheck {
+    match fn_sym {
+        "sin" | "cos" if !cfg!(target_env = "musl") => FloatCheck::Ulps(1),
+        _ => FloatCheck::AnyNan,
+    }
+}
+
+/// a param as it's passed to the native function
+#[derive(Debug)]
+enum Arg {
+    Int(u64),
+    F32(f32),
+    F64(f64),
+}
+
+/// call the function with the exact signature of the params and the result,
+/// only the ones used by the tests are supported
+unsafe fn call_native(
+    addr: u64,
+    args: &[Arg],
+    output: &Return,
+) -> Result<Return> {
+    let addr = addr as usize;
+    let result = match (args, output) {
+        ([Arg::Int(a)], Return::Usize(_)) => {
+            let fun: extern "C" fn(u64) -> u64 = transmute(addr);
+            Return::Usize(fun(*a))
+        }
+        ([Arg::Int(a), Arg::Int(b)], Return::Usize(_)) => {
+            let fun: extern "C" fn(u64, u64) -> u64 = transmute(addr);
+            Return::Usize(fun(*a, *b))
+        }
+        ([Arg::Int(a)], Return::I64(_)) => {
+            let fun: extern "C" fn(u64) -> i64 = transmute(addr);
+            Return::I64(fun(*a))
+        }
+        ([Arg::Int(a)], Return::CString(_)) => {
+            let fun: extern "C" fn(u64) -> *const c_char = transmute(addr);
+            let ptr = fun(*a);
+            if ptr.is_null() {
+                bail!("Function returned NULL")
+            }
+            Return::CString(CStr::from_ptr(ptr).to_bytes().to_vec())
+        }
+        ([Arg::F64(x)], Return::F64(_)) => {
+            let fun: extern "C" fn(f64) -> f64 = transmute(addr);
+            Return::F64(fun(*x))
+        }
+        ([Arg::F64(x), Arg::F64(y)], Return::F64(_)) => {
+            let fun: extern "C" fn(f64, f64) -> f64 = transmute(addr);
+            Return::F64(fun(*x, *y))
+        }
+        ([Arg::F32(x)], Return::F32(_)) => {
+            let fun: extern "C" fn(f32) -> f32 = transmute(addr);
+            Return::F32(fun(*x))
+        }
+        ([Arg::F32(x), Arg::F32(y)], Return::F32(_)) => {
+            let fun: extern "C" fn(f32, f32) -> f32 = transmute(addr);
+            Return::F32(fun(*x, *y))
+        }
+        _ => {
+            bail!("Signature {:?} -> {:?} not supported on host", args, output)
+        }
+    };
+    Ok(result)
+}
+
+/// the reference backend, calling the functions on the host
+#[derive(Default)]
+pub struct Host {}
+
+impl Host {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// the addr of the function in the host libc
+    pub fn lookup_symbol(&self, fn_sym: &str) -> Result<u64> {
+        let name = CString::new(fn_sym)?;
+        let mut addr =
+            unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+        if addr.is_null() && !cfg!(target_env = "musl") {
+            // glibc libm is a separated library, only loaded if it's linked
+            let libm = c"libm.so.6".as_ptr();
+            let libm = unsafe { libc::dlopen(libm, libc::RTLD_NOW) };
+            if !libm.is_null() {
+                addr = unsafe { libc::dlsym(libm, name.as_ptr()) };
+            }
+        }
+        if addr.is_null() {
+            bail!("Function {} not available on the host", fn_sym)
+        }
+        Ok(addr as u64)
+    }
+
+    /// call the function with the inputs, the output is only used for the
+    /// type of the result
+    pub fn call(
+        &mut self,
+        fn_sym: &str,
+        inputs: &[Input],
+        output: &Return,
+    ) -> Result<Return> {
+        let addr = self.lookup_symbol(fn_sym)?;
+        // the functions can write into the data, so it need to be copied
+        let mut heap: Vec<Vec<u8>> = inputs
+            .iter()
+            .map(|input| match input {
+                Input::Bytes(data) => data.clone(),
+                _ => vec![],
+            })
+            .collect();
+        let args: Vec<Arg> = inputs
+            .iter()
+            .zip(heap.iter_mut())
+            .map(|(input, data)| match input {
+                Input::Usize(value) => Arg::Int(*value),
+                Input::I64(value) => Arg::Int(*value as u64),
+                Input::F32(value) => Arg::F32(*value),
+                Input::F64(value) => Arg::F64(*value),
+                Input::Bytes(_) => Arg::Int(data.as_mut_ptr() as u64),
+            })
+            .collect();
+        let result = unsafe { call_native(addr, &args, output) };
+        drop(heap);
+        result
+    }
+}
@@ -1,4 +1,10 @@
 pub mod aarch64;
+pub mod detect;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod host;
 //pub mod arm;
 pub mod x86;
 pub mod x86_64;
@@ -6,7 +6,50 @@ use icicle_mem::perm;
 use icicle_vm;
 use pcode::VarNode;
 
-use crate::vm::{IcicleHelper, Param, Return, Vm};
+use crate::elf::Elf;
+use crate::syscall::{self, SyscallAbi};
+use crate::vm::{Boot, IcicleHelper, Param, Return, TlsVariant, Vm};
+
+/// the syscalls musl may use, with the number of params
+const SYSCALLS: &[(u64, &str, usize)] = &[
+    (1, "exit", 1),
+    (3, "read", 3),
+    (4, "write", 3),
+    (5, "open", 3),
+    (6, "close", 1),
+    (13, "time", 1),
+    (20, "getpid", 0),
+    (37, "kill", 2),
+    (45, "brk", 1),
+    (54, "ioctl", 3),
+    (64, "getppid", 0),
+    (78, "gettimeofday", 2),
+    (91, "munmap", 2),
+    (122, "uname", 1),
+    (125, "mprotect", 3),
+    (140, "_llseek", 5),
+    (145, "readv", 3),
+    (146, "writev", 3),
+    (163, "mremap", 5),
+    (174, "rt_sigaction", 4),
+    (175, "rt_sigprocmask", 4),
+    (191, "ugetrlimit", 2),
+    (192, "mmap2", 6),
+    (199, "getuid32", 0),
+    (200, "getgid32", 0),
+    (201, "geteuid32", 0),
+    (202, "getegid32", 0),
+    (221, "fcntl64", 3),
+    (224, "gettid", 0),
+    (243, "set_thread_area", 1),
+    (252, "exit_group", 1),
+    (258, "set_tid_address", 1),
+    (265, "clock_gettime", 2),
+    (295, "openat", 4),
+    (340, "prlimit64", 4),
+    (355, "getrandom", 3),
+    (403, "clock_gettime64", 2),
+];
 
 pub struct X86 {
     pub helper: IcicleHelper,
@@ -17,13 +60,27 @@ pub struct X86 {
 }
 
 impl X86 {
-    pub fn new(triple: &str, musl: &Path) -> Result<Self> {
+    pub fn new(triple: &str, musl: &Path, boot: Option<&Boot>) -> Result<Self> {
         let mut vm = icicle_vm::build(&icicle_vm::cpu::Config {
             triple: triple.parse().unwrap(),
             enable_shadow_stack: false,
             ..icicle_vm::cpu::Config::default()
         })?;
-        vm.env = icicle_vm::env::build_auto(&mut vm)?;
+        let reg = |name: &str| vm.cpu.arch.sleigh.get_reg(name).unwrap().var;
+        let syscall_abi = SyscallAbi {
+            nr: reg("EAX"),
+            args: [
+                reg("EBX"),
+                reg("ECX"),
+                reg("EDX"),
+                reg("ESI"),
+                reg("EDI"),
+                reg("EBP"),
+            ],
+            ret: reg("EAX"),
+            table: SYSCALLS,
+        };
+        let kernel = syscall::intercept(&mut vm, syscall_abi)?;
         vm.env
             .load(&mut vm.cpu, musl.as_os_str().as_bytes())
             .map_err(|e| anyhow!(e))?;
@@ -32,19 +89,36 @@ impl X86 {
         let edx = vm.cpu.arch.sleigh.get_reg("EDX").unwrap().var;
         let st0 = vm.cpu.arch.sleigh.get_reg("ST0").unwrap().var;
         let esp = vm.cpu.arch.sleigh.get_reg("ESP").unwrap().var;
-        Ok(Self {
-            helper: IcicleHelper::new(
-                vm,
-                0x1000_0000,
-                0x1000_0000,
-                0x2000_0000,
-                0x1000_0000,
-            ),
+        let gs_offset = vm.cpu.arch.sleigh.get_reg("GS_OFFSET").unwrap().var;
+        let mut helper = IcicleHelper::new(
+            vm,
+            musl,
+            0x1000_0000,
+            0x1000_0000,
+            0x2000_0000,
+            0x1000_0000,
+            kernel,
+        );
+        let tp = helper.init_tls(gs_offset, TlsVariant::BelowTp)?;
+        // i386 make the syscalls with `call *%gs:0x10`, the sysinfo after the
+        // self, dtv, prev and next pointers. `__init_tp` set it from
+        // `__sysinfo`, the `int $0x80` stub by default
+        let sysinfo = helper.elf_symbol(&Elf::read(musl)?, "__sysinfo")?;
+        let sysinfo = helper.read_ptr(sysinfo)?;
+        helper.write_ptr(tp + 0x10, sysinfo)?;
+        helper.take_snapshot();
+        let mut vm = Self {
+            helper,
             eax,
             edx,
             st0,
             esp,
-        })
+        };
+        // optionally initialize musl, like the start of a process
+        if let Some(boot) = boot {
+            vm.boot(boot)?;
+        }
+        Ok(vm)
     }
 
     fn stack_used(params: &[Param]) -> u64 {
@@ -193,8 +267,9 @@ impl Vm for X86 {
         params: &mut [Param],
         results: &mut [Return],
     ) -> Result<()> {
-        //clean the heap
-        self.helper.free_all();
+        // return to the state right after the library was loaded, unless
+        // inside a session
+        self.helper.prepare_call();
 
         let stack_addr = self.set_call(return_addr as u32, params)?;
         // set stack addr to register
@@ -202,7 +277,7 @@ impl Vm for X86 {
 
         // set the function addr to pc
         self.helper.icicle.cpu.write_pc(function_addr);
-        let vm_exit = self.helper.icicle.run_until(return_addr);
+        let vm_exit = self.helper.run_until(return_addr);
         if vm_exit != icicle_vm::VmExit::Breakpoint {
             bail!(
                 "Vm exited at 0x{:08x} with {:?}",
@@ -212,7 +287,7 @@ impl Vm for X86 {
         }
 
         self.get_results(results)?;
-        self.helper.icicle.cpu.reset();
+        self.helper.stats.results.extend_from_slice(results);
         Ok(())
     }
 }
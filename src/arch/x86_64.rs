@@ -1,6 +1,7 @@
 use icicle_mem::perm;
 
-use crate::vm::{IcicleHelper, Return};
+use crate::syscall::{self, SyscallAbi};
+use crate::vm::{Boot, IcicleHelper, Return, TlsVariant};
 
 use std::os::unix::prelude::OsStrExt;
 use std::path::Path;
@@ -11,11 +12,52 @@ use pcode::VarNode;
 
 use crate::vm::{Param, Vm};
 
+/// the syscalls musl may use, with the number of params
+const SYSCALLS: &[(u64, &str, usize)] = &[
+    (0, "read", 3),
+    (1, "write", 3),
+    (2, "open", 3),
+    (3, "close", 1),
+    (5, "fstat", 2),
+    (8, "lseek", 3),
+    (9, "mmap", 6),
+    (10, "mprotect", 3),
+    (11, "munmap", 2),
+    (12, "brk", 1),
+    (13, "rt_sigaction", 4),
+    (14, "rt_sigprocmask", 4),
+    (16, "ioctl", 3),
+    (19, "readv", 3),
+    (20, "writev", 3),
+    (25, "mremap", 5),
+    (39, "getpid", 0),
+    (60, "exit", 1),
+    (62, "kill", 2),
+    (63, "uname", 1),
+    (72, "fcntl", 3),
+    (96, "gettimeofday", 2),
+    (97, "getrlimit", 2),
+    (102, "getuid", 0),
+    (104, "getgid", 0),
+    (107, "geteuid", 0),
+    (108, "getegid", 0),
+    (110, "getppid", 0),
+    (158, "arch_prctl", 2),
+    (186, "gettid", 0),
+    (201, "time", 1),
+    (218, "set_tid_address", 1),
+    (228, "clock_gettime", 2),
+    (231, "exit_group", 1),
+    (257, "openat", 4),
+    (302, "prlimit64", 4),
+    (318, "getrandom", 3),
+];
+
 pub struct X86_64 {
     pub helper: IcicleHelper,
     r: [VarNode; 6],
-    xmm_qa: [VarNode; 6],
-    xmm_da: [VarNode; 6],
+    xmm_qa: [VarNode; 8],
+    xmm_da: [VarNode; 8],
     rax: VarNode,
     rsp: VarNode,
 }
@@ -41,13 +83,27 @@ impl X86_64 {
         format!("XMM{}_Qa", idx)
     }
 
-    pub fn new(musl: &Path) -> Result<Self> {
+    pub fn new(musl: &Path, boot: Option<&Boot>) -> Result<Self> {
         let mut vm = icicle_vm::build(&icicle_vm::cpu::Config {
             triple: "x86_64-linux-musl".parse().unwrap(),
             enable_shadow_stack: false,
             ..icicle_vm::cpu::Config::default()
         })?;
-        vm.env = icicle_vm::env::build_auto(&mut vm)?;
+        let reg = |name: &str| vm.cpu.arch.sleigh.get_reg(name).unwrap().var;
+        let syscall_abi = SyscallAbi {
+            nr: reg("RAX"),
+            args: [
+                reg("RDI"),
+                reg("RSI"),
+                reg("RDX"),
+                reg("R10"),
+                reg("R8"),
+                reg("R9"),
+            ],
+            ret: reg("RAX"),
+            table: SYSCALLS,
+        };
+        let kernel = syscall::intercept(&mut vm, syscall_abi)?;
         vm.env
             .load(&mut vm.cpu, musl.as_os_str().as_bytes())
             .map_err(|e| anyhow!(e))?;
@@ -57,36 +113,48 @@ impl X86_64 {
             .collect::<Vec<_>>()
             .try_into()
             .unwrap();
-        let xmm_qa = (0..6)
+        let xmm_qa = (0..8)
             .map(|i| vm.cpu.arch.sleigh.get_reg(&Self::xmm_qa(i)).unwrap().var)
             .collect::<Vec<_>>()
             .try_into()
             .unwrap();
-        let xmm_da = (0..6)
+        let xmm_da = (0..8)
             .map(|i| vm.cpu.arch.sleigh.get_reg(&Self::xmm_da(i)).unwrap().var)
             .collect::<Vec<_>>()
             .try_into()
             .unwrap();
         let rax = vm.cpu.arch.sleigh.get_reg("RAX").unwrap().var;
         let rsp = vm.cpu.arch.sleigh.get_reg("RSP").unwrap().var;
-        Ok(Self {
-            helper: IcicleHelper::new(
-                vm,
-                0x1000_0000,
-                0x1000_0000,
-                0x2000_0000,
-                0x1000_0000,
-            ),
+        let fs_offset = vm.cpu.arch.sleigh.get_reg("FS_OFFSET").unwrap().var;
+        let mut helper = IcicleHelper::new(
+            vm,
+            musl,
+            0x1000_0000,
+            0x1000_0000,
+            0x2000_0000,
+            0x1000_0000,
+            kernel,
+        );
+        helper.init_tls(fs_offset, TlsVariant::BelowTp)?;
+        helper.take_snapshot();
+        let mut vm = Self {
+            helper,
             rax,
             rsp,
             r,
             xmm_qa,
             xmm_da,
-        })
+        };
+        // optionally initialize musl, like the start of a process
+        if let Some(boot) = boot {
+            vm.boot(boot)?;
+        }
+        Ok(vm)
     }
 
     fn stack_used(params: &[Param]) -> u64 {
-        if params.len() > 6 {
+        let floats = params.iter().filter(|param| param.is_float()).count();
+        if params.len() - floats > 6 || floats > 8 {
             todo!()
         }
         // 8 for the return address added to the stack
@@ -98,20 +166,19 @@ impl X86_64 {
         return_addr: u64,
         params: &mut [Param],
     ) -> Result<u64> {
-        if params.len() > 6 {
-            todo!()
-        }
         //TODO min len for the stack
         let stack_len = Self::stack_used(params).max(0x1000);
         self.helper.set_stack_len(stack_len)?;
 
         let mut stack_pos = self.helper.stack_addr + self.helper.stack_size;
         // TODO: https://gitlab.com/x86-psABIs/x86-64-ABI/-/jobs/artifacts/master/raw/x86-64-ABI/abi.pdf?job=build
-        for (i, param) in params.iter_mut().enumerate() {
-            match param {
-                Param::Usize(value) => {
-                    self.helper.icicle.cpu.write_reg(self.r[i], *value)
-                }
+        // the ints and the floats take the next free register of their own
+        // class, `f(int, double, int)` is rdi, xmm0 and rsi
+        let mut r = self.r.into_iter();
+        let mut xmm = self.xmm_qa.into_iter().zip(self.xmm_da);
+        for param in params.iter_mut() {
+            let (reg, value) = match param {
+                Param::Usize(value) => (r.next(), *value),
                 Param::HeapData(data) => {
                     let addr = self.helper.malloc(data.len() as u64)?;
                     // write the heap
@@ -121,29 +188,32 @@ impl X86_64 {
                         perm::NONE,
                     )?;
                     // put the addr to the reg
-                    self.helper.icicle.cpu.write_reg(self.r[i], addr)
+                    (r.next(), addr)
                 }
                 Param::HeapFn(write_data) => {
-                    let addr = write_data(&mut self.helper)?;
                     // put the addr to the reg
-                    self.helper.icicle.cpu.write_reg(self.r[i], addr)
+                    (r.next(), write_data(&mut self.helper)?)
                 }
-                Param::F32(value) => self
-                    .helper
-                    .icicle
-                    .cpu
-                    .write_reg(self.xmm_da[i], value.to_bits() as u64),
-                Param::F64(value) => self
-                    .helper
-                    .icicle
-                    .cpu
-                    .write_reg(self.xmm_qa[i], value.to_bits()),
-                Param::I64(value) => {
-                    self.helper.icicle.cpu.write_reg(self.r[i], *value as u64)
+                Param::F32(value) => {
+                    (xmm.next().map(|(_, da)| da), value.to_bits() as u64)
                 }
-            }
+                Param::F64(value) => {
+                    (xmm.next().map(|(qa, _)| qa), value.to_bits())
+                }
+                Param::I64(value) => (r.next(), *value as u64),
+            };
+            // `stack_used` already checked that all of them fit
+            self.helper.icicle.cpu.write_reg(reg.unwrap(), value);
         }
 
+        // variadic functions receive the number of vector registers used in al
+        let vector_regs =
+            params.iter().filter(|param| param.is_float()).count();
+        self.helper
+            .icicle
+            .cpu
+            .write_reg(self.rax, vector_regs as u64);
+
         // add the return addr to the stack
         stack_pos -= 8;
         self.helper
@@ -204,8 +274,9 @@ impl Vm for X86_64 {
         params: &mut [Param],
         results: &mut [Return],
     ) -> Result<()> {
-        //clean the heap
-        self.helper.free_all();
+        // return to the state right after the library was loaded, unless
+        // inside a session
+        self.helper.prepare_call();
 
         let stack_addr = self.set_call(return_addr, params)?;
         // set stack addr to register
@@ -213,7 +284,7 @@ impl Vm for X86_64 {
 
         // set the function addr to pc
         self.helper.icicle.cpu.write_pc(function_addr);
-        let vm_exit = self.helper.icicle.run_until(return_addr);
+        let vm_exit = self.helper.run_until(return_addr);
         if vm_exit != icicle_vm::VmExit::Breakpoint {
             bail!(
                 "Vm exited at 0x{:016x} with {:?}",
@@ -223,7 +294,7 @@ impl Vm for X86_64 {
         }
 
         self.get_results(results)?;
-        self.helper.icicle.cpu.reset();
+        self.helper.stats.results.extend_from_slice(results);
         Ok(())
     }
 }
@@ -0,0 +1,35 @@
+//! Where to find the musl binaries.
+//!
+//! The directory is taken from `PINGU_BINS`, then from the `bins` key of
+//! `pingu.conf` in the crate root, and by default it's the `bins` directory of
+//! the crate.
+
+use std::path::{Path, PathBuf};
+
+const CONFIG: &str = "pingu.conf";
+
+/// the `bins = <dir>` line of the config, relative to the crate root
+fn from_config(root: &Path) -> Option<PathBuf> {
+    let config = std::fs::read_to_string(root.join(CONFIG)).ok()?;
+    config
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "bins").then(|| root.join(value.trim()))
+        })
+}
+
+pub fn dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("PINGU_BINS") {
+        return dir.into();
+    }
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    from_config(root).unwrap_or_else(|| root.join("bins"))
+}
+
+/// the path of the binary, None if it don't exist
+pub fn find(file: &str) -> Option<PathBuf> {
+    let path = dir().join(file);
+    path.is_file().then_some(path)
+}
@@ -0,0 +1,114 @@
+//! Compare the reports of every arch. A case that is not the same on every
+//! arch is an icicle bug, even for functions without an exact reference.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::report::{Case, Outcome as CaseOutcome, Report};
+use crate::vm::Return;
+
+/// what a case did on one arch
+#[derive(Debug)]
+pub enum Outcome {
+    /// the case passed, with the results of the calls. The [`Return::Usize`]
+    /// are not included, pointers and `long` depend on the arch, and the case
+    /// already checked them
+    Pass(Vec<Return>),
+    /// the case failed, with the actual value
+    Fail(String),
+    Error(String),
+}
+
+impl Outcome {
+    fn new(case: &Case) -> Self {
+        match &case.outcome {
+            CaseOutcome::Pass => Outcome::Pass(
+                case.stats
+                    .results
+                    .iter()
+                    .filter(|result| !matches!(result, Return::Usize(_)))
+                    .cloned()
+                    .collect(),
+            ),
+            CaseOutcome::Fail { actual, .. } => Outcome::Fail(actual.clone()),
+            CaseOutcome::Error(error) => Outcome::Error(error.clone()),
+        }
+    }
+
+    /// an error don't agree with anything, not even the same error, that
+    /// would hide a fault shared by every arch
+    fn same_as(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Pass(x), Outcome::Pass(y)) => {
+                x.len() == y.len() && x.iter().zip(y).all(|(x, y)| x.same_as(y))
+            }
+            (Outcome::Fail(x), Outcome::Fail(y)) => x == y,
+            _ => false,
+        }
+    }
+}
+
+/// the archs that don't agree with the majority
+pub struct Divergence {
+    pub majority: Outcome,
+    pub archs: Vec<(String, Outcome)>,
+}
+
+/// the archs with an outcome different from the majority, if any. A case
+/// that is an error on every arch also diverges
+fn divergence(mut outcomes: Vec<(String, Outcome)>) -> Option<Divergence> {
+    // the outcome that most archs agree with
+    let agree = |outcome: &Outcome| {
+        outcomes.iter().filter(|(_, x)| x.same_as(outcome)).count()
+    };
+    let majority_idx =
+        (0..outcomes.len()).max_by_key(|i| agree(&outcomes[*i].1))?;
+    let (_, majority) = outcomes.swap_remove(majority_idx);
+    let archs: Vec<_> = outcomes
+        .into_iter()
+        .filter(|(_, outcome)| !outcome.same_as(&majority))
+        .collect();
+    if archs.is_empty() && !matches!(majority, Outcome::Error(_)) {
+        return None;
+    }
+    Some(Divergence { majority, archs })
+}
+
+/// compare every case with the same case of the other archs, a case fails if
+/// any arch don't agree with the majority
+pub fn compare(reports: &[Report], report: &mut Report) {
+    let archs: Vec<HashMap<(&str, &str), &Case>> = reports
+        .iter()
+        .map(|report| {
+            let cases = report.cases.iter();
+            cases
+                .map(|case| ((case.fn_sym, case.name.as_str()), case))
+                .collect()
+        })
+        .collect();
+    // every case, in the order of the first report that has it
+    let mut seen = HashSet::new();
+    let cases = reports
+        .iter()
+        .flat_map(|report| &report.cases)
+        .filter(|case| seen.insert((case.fn_sym, case.name.as_str())));
+    for case in cases {
+        let key = (case.fn_sym, case.name.as_str());
+        // some cases only run on a few archs
+        let outcomes = reports
+            .iter()
+            .zip(&archs)
+            .filter_map(|(report, cases)| {
+                Some((report.arch.clone(), Outcome::new(cases.get(&key)?)))
+            })
+            .collect();
+        let outcome = match divergence(outcomes) {
+            None => CaseOutcome::Pass,
+            Some(divergence) => CaseOutcome::Fail {
+                expected: format!("{:?}", divergence.majority),
+                actual: format!("{:?}", divergence.archs),
+            },
+        };
+        let name = format!("diff {}", case.name);
+        report.record(case.fn_sym, name, Ok(outcome));
+    }
+}
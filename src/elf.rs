@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_TLS: u32 = 7;
+
+pub const DT_INIT: u64 = 12;
+pub const DT_INIT_ARRAY: u64 = 25;
+pub const DT_INIT_ARRAYSZ: u64 = 27;
+
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_DYNSYM: u32 = 11;
+
+pub const EM_386: u16 = 3;
+pub const EM_MIPS: u16 = 8;
+pub const EM_PPC: u16 = 20;
+pub const EM_PPC64: u16 = 21;
+pub const EM_ARM: u16 = 40;
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+pub const EM_RISCV: u16 = 243;
+
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+/// the few parts of the ELF file the tester need
+pub struct Elf {
+    pub data: Vec<u8>,
+    pub class64: bool,
+    pub big_endian: bool,
+    /// the `e_machine`, one of the `EM_*` values
+    pub machine: u16,
+    /// the `e_flags`, the meaning depends on the machine
+    pub flags: u32,
+    pub program_headers: Vec<ProgramHeader>,
+}
+
+impl Elf {
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::parse(std::fs::read(path)?)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if data.len() < 0x34 || &data[0..4] != b"\x7fELF" {
+            bail!("Invalid ELF header")
+        }
+        let class64 = match data[4] {
+            1 => false,
+            2 => true,
+            x => bail!("Invalid ELF class {}", x),
+        };
+        let big_endian = match data[5] {
+            1 => false,
+            2 => true,
+            x => bail!("Invalid ELF data {}", x),
+        };
+        let mut elf = Self {
+            data,
+            class64,
+            big_endian,
+            machine: 0,
+            flags: 0,
+            program_headers: vec![],
+        };
+        elf.machine = elf.uint(0x12, 2)? as u16;
+        elf.flags = elf.uint(if class64 { 0x30 } else { 0x24 }, 4)? as u32;
+        let (phoff, phentsize, phnum) = if class64 {
+            (elf.uint(0x20, 8)?, elf.uint(0x36, 2)?, elf.uint(0x38, 2)?)
+        } else {
+            (elf.uint(0x1c, 4)?, elf.uint(0x2a, 2)?, elf.uint(0x2c, 2)?)
+        };
+        let program_headers = (0..phnum)
+            .map(|i| elf.program_header(phoff + (i * phentsize)))
+            .collect::<Result<_>>()?;
+        elf.program_headers = program_headers;
+        Ok(elf)
+    }
+
+    /// read an unsigned int with `len` bytes at `offset` of the file
+    pub fn uint(&self, offset: u64, len: u64) -> Result<u64> {
+        let start = usize::try_from(offset)?;
+        let end = start + usize::try_from(len)?;
+        let Some(bytes) = self.data.get(start..end) else {
+            bail!("ELF offset 0x{:x} is out of bounds", offset)
+        };
+        let value = if self.big_endian {
+            bytes.iter().fold(0, |acc, x| (acc << 8) | u64::from(*x))
+        } else {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |acc, x| (acc << 8) | u64::from(*x))
+        };
+        Ok(value)
+    }
+
+    fn program_header(&self, offset: u64) -> Result<ProgramHeader> {
+        if self.class64 {
+            Ok(ProgramHeader {
+                p_type: self.uint(offset, 4)? as u32,
+                offset: self.uint(offset + 0x08, 8)?,
+                vaddr: self.uint(offset + 0x10, 8)?,
+                filesz: self.uint(offset + 0x20, 8)?,
+                memsz: self.uint(offset + 0x28, 8)?,
+                align: self.uint(offset + 0x30, 8)?,
+            })
+        } else {
+            Ok(ProgramHeader {
+                p_type: self.uint(offset, 4)? as u32,
+                offset: self.uint(offset + 0x04, 4)?,
+                vaddr: self.uint(offset + 0x08, 4)?,
+                filesz: self.uint(offset + 0x10, 4)?,
+                memsz: self.uint(offset + 0x14, 4)?,
+                align: self.uint(offset + 0x1c, 4)?,
+            })
+        }
+    }
+
+    /// the name musl uses for the arch, from the `/etc/ld-musl-<name>.path`
+    /// string in the loader, eg: `armhf` or `mips64-sf`
+    pub fn musl_name(&self) -> Option<&str> {
+        const PREFIX: &[u8] = b"/etc/ld-musl-";
+        let start = self
+            .data
+            .windows(PREFIX.len())
+            .position(|window| window == PREFIX)?
+            + PREFIX.len();
+        let len = self.data[start..].iter().position(|c| *c == 0)?;
+        let path = std::str::from_utf8(&self.data[start..start + len]).ok()?;
+        path.strip_suffix(".path")
+    }
+
+    /// the type, offset, size and link of the section `i`
+    fn section_header(&self, i: u64) -> Result<(u32, u64, u64, u64)> {
+        let (shoff, shentsize) = if self.class64 {
+            (self.uint(0x28, 8)?, self.uint(0x3a, 2)?)
+        } else {
+            (self.uint(0x20, 4)?, self.uint(0x2e, 2)?)
+        };
+        let offset = shoff + (i * shentsize);
+        if self.class64 {
+            Ok((
+                self.uint(offset + 0x04, 4)? as u32,
+                self.uint(offset + 0x18, 8)?,
+                self.uint(offset + 0x20, 8)?,
+                self.uint(offset + 0x28, 4)?,
+            ))
+        } else {
+            Ok((
+                self.uint(offset + 0x04, 4)? as u32,
+                self.uint(offset + 0x10, 4)?,
+                self.uint(offset + 0x14, 4)?,
+                self.uint(offset + 0x18, 4)?,
+            ))
+        }
+    }
+
+    /// the value of a symbol, from the `.symtab` and then the `.dynsym`, so
+    /// the LOCAL symbols, like `__init_libc`, are also found if the library
+    /// is not stripped
+    pub fn symbol(&self, name: &str) -> Result<Option<u64>> {
+        let shnum = self.uint(if self.class64 { 0x3c } else { 0x30 }, 2)?;
+        let (sym_len, value_offset, value_len) =
+            if self.class64 { (24, 8, 8) } else { (16, 4, 4) };
+        for table in [SHT_SYMTAB, SHT_DYNSYM] {
+            for i in 0..shnum {
+                let (sh_type, offset, size, link) = self.section_header(i)?;
+                if sh_type != table {
+                    continue;
+                }
+                // the names are in the linked string table
+                let (_, strtab, strtab_len, _) = self.section_header(link)?;
+                for sym in (offset..offset + size).step_by(sym_len) {
+                    let name_offset = self.uint(sym, 4)?;
+                    if name_offset >= strtab_len {
+                        continue;
+                    }
+                    let start = usize::try_from(strtab + name_offset)?;
+                    let Some(names) = self.data.get(start..) else {
+                        continue;
+                    };
+                    if names.split(|c| *c == 0).next() == Some(name.as_bytes())
+                    {
+                        return self
+                            .uint(sym + value_offset, value_len)
+                            .map(Some);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// the value of a tag of the PT_DYNAMIC segment, if it's there
+    pub fn dynamic(&self, tag: u64) -> Result<Option<u64>> {
+        let Some(ph) = self
+            .program_headers
+            .iter()
+            .find(|ph| ph.p_type == PT_DYNAMIC)
+        else {
+            return Ok(None);
+        };
+        let len = if self.class64 { 8 } else { 4 };
+        for entry in
+            (ph.offset..ph.offset + ph.filesz).step_by(2 * len as usize)
+        {
+            match self.uint(entry, len)? {
+                // DT_NULL, the end of the table
+                0 => break,
+                d_tag if d_tag == tag => {
+                    return self.uint(entry + len, len).map(Some)
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// the PT_TLS segment, if any
+    pub fn tls(&self) -> Option<&ProgramHeader> {
+        self.program_headers.iter().find(|ph| ph.p_type == PT_TLS)
+    }
+
+    /// the initialization image of a segment, the rest, up to `memsz`, is
+    /// zeroed
+    pub fn segment_data(&self, ph: &ProgramHeader) -> Result<&[u8]> {
+        let start = usize::try_from(ph.offset)?;
+        let end = start + usize::try_from(ph.filesz)?;
+        match self.data.get(start..end) {
+            Some(data) => Ok(data),
+            None => bail!("ELF segment at 0x{:x} is out of bounds", ph.offset),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Golden snapshots with the raw results of every case.
+//!
+//! With `PINGU_GOLDEN=record` the results of each arch are written to
+//! `golden/<arch>.txt`, otherwise they are compared with that file, if it
+//! was recorded. This shows exactly what changed after updating icicle, even
+//! for functions without an exact reference, like `sin`. The random cases are
+//! not included, they change with the seed.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::report::{check as check_value, Case, Outcome, Report};
+use crate::vm::Return;
+
+fn path(arch: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(format!("{}.txt", arch))
+}
+
+/// the bits of the results, floats are not formatted, so any change is
+/// visible
+fn results(case: &Case) -> String {
+    if let Outcome::Error(_) = case.outcome {
+        return "error".to_string();
+    }
+    let results: Vec<_> = case
+        .stats
+        .results
+        .iter()
+        .map(|result| match result {
+            Return::Usize(value) => format!("usize:{:#x}", value),
+            Return::I64(value) => format!("i64:{:#x}", value),
+            Return::F32(value) => format!("f32:{:#010x}", value.to_bits()),
+            Return::F64(value) => format!("f64:{:#018x}", value.to_bits()),
+            Return::CString(data) => {
+                let hex: String =
+                    data.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("cstr:{}", hex)
+            }
+        })
+        .collect();
+    results.join(" ")
+}
+
+/// the cases that are the same on every run, with the results
+fn snapshot(report: &Report) -> Vec<(&'static str, &str, String)> {
+    report
+        .cases
+        .iter()
+        .filter(|case| !case.name.starts_with("random "))
+        .map(|case| (case.fn_sym, case.name.as_str(), results(case)))
+        .collect()
+}
+
+fn record(report: &Report) -> Result<()> {
+    let path = path(&report.arch);
+    let mut data = String::new();
+    for (fn_sym, name, results) in snapshot(report) {
+        writeln!(data, "{}\t{}\t{}", fn_sym, name, results).unwrap();
+    }
+    let dir = path.parent().unwrap();
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("unable to create {}", dir.display()))?;
+    std::fs::write(&path, data)
+        .with_context(|| format!("unable to write {}", path.display()))
+}
+
+fn verify(report: &mut Report) -> Result<()> {
+    let path = path(&report.arch);
+    if !path.exists() {
+        println!(
+            "skipping the golden snapshot, {} is missing, record it with \
+             PINGU_GOLDEN=record",
+            path.display()
+        );
+        return Ok(());
+    }
+    let data = std::fs::read_to_string(&path)
+        .with_context(|| format!("unable to read {}", path.display()))?;
+    // the results never have tabs, the name could
+    let mut golden = BTreeMap::new();
+    for line in data.lines() {
+        let (case, results) = line.rsplit_once('\t').with_context(|| {
+            format!("invalid line in {}: {:?}", path.display(), line)
+        })?;
+        golden.insert(case.to_string(), results.to_string());
+    }
+
+    let changes: Vec<_> = snapshot(report)
+        .into_iter()
+        .filter_map(|(fn_sym, name, results)| {
+            // new cases are not in the snapshot yet
+            let expected = golden.remove(&format!("{}\t{}", fn_sym, name))?;
+            let outcome = check_value(expected, results);
+            (!outcome.is_pass()).then(|| (fn_sym, name.to_string(), outcome))
+        })
+        .collect();
+    for (fn_sym, name, outcome) in changes {
+        report.record(fn_sym, format!("golden {}", name), Ok(outcome));
+    }
+    // cases that are gone, the key is `fn_sym\tname`
+    for (case, results) in golden {
+        let name = format!("golden {}", case.replace('\t', " "));
+        let outcome = check_value(results.as_str(), "missing");
+        report.record("golden", name, Ok(outcome));
+    }
+    Ok(())
+}
+
+/// record or verify the snapshot of the arch, the changed results are added
+/// to the report as failures
+pub fn check(report: &mut Report) {
+    let result = match std::env::var("PINGU_GOLDEN").as_deref() {
+        Ok("record") => record(report),
+        _ => verify(report),
+    };
+    if let Err(error) = result {
+        report.record("golden", "snapshot", Err(error));
+    }
+}
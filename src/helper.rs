@@ -1,5 +1,7 @@
 use anyhow::Result;
-use icicle_mem::Mapping;
+use icicle_mem::{perm, Mapping};
+use icicle_vm::cpu::Cpu;
+use target_lexicon::Endianness;
 
 pub fn create_empty_memory(
     mem: &mut icicle_mem::Mmu,
@@ -27,6 +29,44 @@ pub fn create_empty_memory(
     Ok((addr, blocks * page_size))
 }
 
+/// size of a pointer in the guest
+pub fn ptr_size(cpu: &Cpu) -> u64 {
+    cpu.arch.triple.pointer_width().unwrap().bytes().into()
+}
+
+fn big_endian(cpu: &Cpu) -> bool {
+    cpu.arch.triple.endianness() == Ok(Endianness::Big)
+}
+
+/// read an unsigned int with `len` bytes, in the guest endianess
+pub fn read_uint(cpu: &mut Cpu, addr: u64, len: u64) -> Result<u64> {
+    let mut bytes = vec![0u8; len.try_into().unwrap()];
+    cpu.mem.read_bytes(addr, &mut bytes, perm::NONE)?;
+    if !big_endian(cpu) {
+        bytes.reverse();
+    }
+    Ok(bytes
+        .into_iter()
+        .fold(0, |acc, x| (acc << 8) | u64::from(x)))
+}
+
+/// write an unsigned int with `len` bytes, in the guest endianess
+pub fn write_uint(
+    cpu: &mut Cpu,
+    addr: u64,
+    value: u64,
+    len: u64,
+) -> Result<()> {
+    let len: usize = len.try_into().unwrap();
+    let bytes = if big_endian(cpu) {
+        value.to_be_bytes()[8 - len..].to_vec()
+    } else {
+        value.to_le_bytes()[..len].to_vec()
+    };
+    cpu.mem.write_bytes(addr, &bytes, perm::NONE)?;
+    Ok(())
+}
+
 //pub fn create_null(mem: &mut icicle_mem::Mmu) -> Result<u64> {
 //    create_empty_memory(mem, Some(0), 1024, perm::NONE).map(|(addr, _)| addr)
 //}
@@ -1,76 +1,216 @@
 #[cfg(test)]
+mod bins;
+#[cfg(test)]
+mod diff;
+#[cfg(test)]
+mod elf;
+#[cfg(test)]
+mod golden;
+#[cfg(test)]
 mod helper;
 #[cfg(test)]
+mod random;
+#[cfg(test)]
+mod report;
+#[cfg(test)]
+mod shrink;
+#[cfg(test)]
 pub mod vm;
 
 #[cfg(test)]
 pub mod arch;
 #[cfg(test)]
+pub mod syscall;
+#[cfg(test)]
 pub mod test;
 
 #[cfg(test)]
 mod tests {
     use crate::arch::*;
+    use crate::report::Report;
     use crate::test::*;
-    use crate::vm::Vm;
+    use crate::vm::{Boot, Vm};
+    use crate::{bins, diff, golden};
     use anyhow::Result;
-    use std::path::Path;
-
-    fn test(mut vm: impl Vm) -> Result<bool> {
-        let mut result = true;
-        result &= strlen::all_tests(&mut vm)?;
-        result &= strcat::all_tests(&mut vm)?;
-        result &= atoll::all_tests(&mut vm)?;
-        result &= cos::all_tests(&mut vm)?;
-        result &= sin::all_tests(&mut vm)?;
-        result &= rint::all_tests(&mut vm)?;
-        result &= rintf::all_tests(&mut vm)?;
-        Ok(result)
+    use std::path::{Path, PathBuf};
+
+    fn test(arch: &str, mut vm: impl Vm) -> Report {
+        let mut report = Report::new(arch);
+        run_tests(&mut vm, &mut report);
+        golden::check(&mut report);
+        report
+    }
+
+    /// every test module, the same cases on every arch
+    fn run_tests(vm: &mut impl Vm, report: &mut Report) {
+        strlen::all_tests(vm, report);
+        strcat::all_tests(vm, report);
+        mem::all_tests(vm, report);
+        strcmp::all_tests(vm, report);
+        strsearch::all_tests(vm, report);
+        strcopy::all_tests(vm, report);
+        atoll::all_tests(vm, report);
+        strtol::all_tests(vm, report);
+        strtod::all_tests(vm, report);
+        cos::all_tests(vm, report);
+        sin::all_tests(vm, report);
+        rint::all_tests(vm, report);
+        rintf::all_tests(vm, report);
+        fenv::all_tests(vm, report);
+        libm::all_tests(vm, report);
+        fpclassify::all_tests(vm, report);
+        rand::all_tests(vm, report);
+        strtok::all_tests(vm, report);
+        setjmp::all_tests(vm, report);
+        errno::all_tests(vm, report);
+        getenv::all_tests(vm, report);
+        sysconf::all_tests(vm, report);
+        fake_kernel::all_tests(vm, report);
+        printf::all_tests(vm, report);
+        snprintf::all_tests(vm, report);
+    }
+
+    /// print the summary table and write the report files, then fail if any
+    /// case didn't pass
+    fn assert_success(report: &Report) -> Result<()> {
+        println!("{}", report);
+        report.write()?;
+        assert!(report.success());
+        Ok(())
+    }
+
+    /// the path of the binary, or None if it's missing, so the test is
+    /// skipped instead of failing on machines without it
+    fn find_bin(file: &str) -> Option<PathBuf> {
+        let musl = bins::find(file);
+        if musl.is_none() {
+            println!("skipping {}, not in {}", file, bins::dir().display());
+        }
+        musl
+    }
+
+    /// the binaries built for a cpu older then i686, their ELF header is the
+    /// same as the i686 one, so they can only be listed here
+    const PRE_I686: &[&str] = &["i486-linux-musl-libc.so"];
+
+    /// the vm for the binary, None if it's missing or there is no backend
+    /// for it
+    fn build(musl: &Path) -> Result<Option<Box<dyn Vm>>> {
+        let pre_i686 = musl
+            .file_name()
+            .is_some_and(|name| PRE_I686.iter().any(|file| name == *file));
+        let vm = detect::build(musl, pre_i686, Some(&Boot::default()))?;
+        if vm.is_none() {
+            println!("skipping {}, no backend for it", musl.display());
+        }
+        Ok(vm)
+    }
+
+    /// run all the tests with the `<arch>-linux-musl-libc.so` binary
+    fn test_arch(arch: &str) -> Result<()> {
+        let file = format!("{}-linux-musl-libc.so", arch);
+        let Some(musl) = find_bin(&file) else { return Ok(()) };
+        let Some(vm) = build(&musl)? else { return Ok(()) };
+        assert_success(&test(arch, vm))
+    }
+
+    /// every binary with a backend, the arch name is the start of the file
+    /// name, eg: `i486`. The binaries that fail to load are skipped
+    fn backends() -> Result<Vec<(String, Box<dyn Vm>)>> {
+        let mut files = std::fs::read_dir(bins::dir())?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        files.sort();
+        let mut vms = vec![];
+        for musl in files {
+            let Some(name) = musl.file_name().and_then(|name| name.to_str())
+            else {
+                continue;
+            };
+            if !name.ends_with(".so") {
+                continue;
+            }
+            let vm = match build(&musl) {
+                Ok(Some(vm)) => vm,
+                Ok(None) => continue,
+                Err(error) => {
+                    println!("skipping {}, {:#}", musl.display(), error);
+                    continue;
+                }
+            };
+            let arch = name.split('-').next().unwrap().to_string();
+            vms.push((arch, vm));
+        }
+        Ok(vms)
     }
 
     #[test]
     fn i486() -> Result<()> {
-        //NOTE there is no i486 triple, just use the i586 instead
-        let vm = x86::X86::new(
-            "i586-linux-musl",
-            Path::new(
-                "/home/rbran/src/icicle-pingu/bins/i486-linux-musl-libc.so",
-            ),
-        )?;
-        assert!(test(vm)?);
-        Ok(())
+        test_arch("i486")
     }
 
     #[test]
     fn i686() -> Result<()> {
-        let vm = x86::X86::new(
-            "i686-linux-musl",
-            Path::new(
-                "/home/rbran/src/icicle-pingu/bins/i686-linux-musl-libc.so",
-            ),
-        )?;
-        assert!(test(vm)?);
-        Ok(())
+        test_arch("i686")
+    }
+
+    /// i386 make every syscall with `call *%gs:0x10`, so this fails if the
+    /// `struct pthread` is not set up like musl does
+    #[test]
+    fn i686_syscall() -> Result<()> {
+        let file = "i686-linux-musl-libc.so";
+        let Some(musl) = find_bin(file) else { return Ok(()) };
+        let Some(mut vm) = build(&musl)? else { return Ok(()) };
+        let mut report = Report::new("i686_syscall");
+        fake_kernel::all_tests(&mut vm, &mut report);
+        assert_success(&report)
     }
 
     #[test]
     fn x86_64() -> Result<()> {
-        let vm = x86_64::X86_64::new(Path::new(
-            "/home/rbran/src/icicle-pingu/bins/x86_64-linux-musl-libc.so",
-        ))?;
-        assert!(test(vm)?);
-        Ok(())
+        test_arch("x86_64")
     }
 
     #[test]
     fn aarch64() -> Result<()> {
-        let vm = aarch64::Aarch64::new(
-            "aarch64-linux-musl",
-            Path::new(
-                "/home/rbran/src/icicle-pingu/bins/aarch64-linux-musl-libc.so",
-            ),
-        )?;
-        assert!(test(vm)?);
-        Ok(())
+        test_arch("aarch64")
+    }
+
+    /// run every test on every arch, and compare the results of each case
+    /// with the other archs
+    #[test]
+    fn cross_arch() -> Result<()> {
+        let vms = backends()?;
+        if vms.len() < 2 {
+            println!("skipping cross_arch, not enough binaries");
+            return Ok(());
+        }
+        let reports: Vec<_> = vms
+            .into_iter()
+            .map(|(arch, mut vm)| {
+                let mut report = Report::new(&arch);
+                run_tests(&mut vm, &mut report);
+                report
+            })
+            .collect();
+        let mut report = Report::new("cross_arch");
+        diff::compare(&reports, &mut report);
+        assert_success(&report)
+    }
+
+    /// compare the emulated results with the host libc
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn x86_64_host() -> Result<()> {
+        let file = "x86_64-linux-musl-libc.so";
+        let Some(musl) = find_bin(file) else { return Ok(()) };
+        let mut vm = x86_64::X86_64::new(&musl, None)?;
+        let mut host = crate::arch::host::Host::new();
+        let mut report = Report::new("x86_64_host");
+        crate::test::host::all_tests(&mut vm, &mut host, &mut report);
+        assert_success(&report)
     }
 }
@@ -0,0 +1,171 @@
+//! Seeded random inputs for the tests.
+//!
+//! The seed is taken from `PINGU_SEED` (decimal or `0x` hex), or is a fixed
+//! one if not set, so every run is the same. With `PINGU_SEED=random` the seed
+//! comes from the clock. It's the same for the whole test run, so a failure
+//! can be replayed with `PINGU_SEED=<seed> cargo test`. The number of random
+//! cases for each test is taken from `PINGU_CASES`.
+
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CASES: usize = 256;
+/// used if `PINGU_SEED` is not set
+const DEFAULT_SEED: u64 = 0x7069_6e67_7573_6565;
+
+/// the seed used by this test run
+pub fn seed() -> u64 {
+    static SEED: OnceLock<u64> = OnceLock::new();
+    *SEED.get_or_init(|| {
+        let Ok(seed) = std::env::var("PINGU_SEED") else {
+            return DEFAULT_SEED;
+        };
+        if seed == "random" {
+            let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            return time.as_nanos() as u64
+                ^ (u64::from(std::process::id()) << 32);
+        }
+        let seed = match seed.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => seed.parse(),
+        };
+        seed.expect("PINGU_SEED is not a valid u64")
+    })
+}
+
+/// number of random cases for each test
+pub fn cases() -> usize {
+    std::env::var("PINGU_CASES")
+        .map(|cases| cases.parse().expect("PINGU_CASES is not a valid usize"))
+        .unwrap_or(DEFAULT_CASES)
+}
+
+/// splitmix64, small and good enough for tests
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// the generator for this test run, each function gets a different one so
+    /// adding cases to one test don't change the others
+    pub fn for_fn(fn_sym: &str) -> Self {
+        let mut rng = Self::new(seed());
+        for byte in fn_sym.bytes() {
+            rng.state ^= u64::from(byte);
+            rng.next_u64();
+        }
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// a value in `0..max`
+    pub fn below(&mut self, max: u64) -> u64 {
+        self.next_u64() % max
+    }
+
+    pub fn choose<'a, T>(&mut self, values: &'a [T]) -> &'a T {
+        &values[self.below(values.len() as u64) as usize]
+    }
+
+    /// a random byte, with a lot of NULs and high bit bytes
+    pub fn byte(&mut self) -> u8 {
+        match self.below(8) {
+            0 => 0,
+            1 | 2 => 0x80 | self.next_u64() as u8,
+            _ => 1 + self.below(0x7f) as u8,
+        }
+    }
+
+    /// a string with up to `max_len` bytes, that may include NULs, always
+    /// terminated by a NUL
+    pub fn c_string(&mut self, max_len: u64) -> Vec<u8> {
+        let len = self.below(max_len + 1);
+        let mut data: Vec<u8> = (0..len).map(|_| self.byte()).collect();
+        data.push(0);
+        data
+    }
+
+    /// a NUL terminated string for `atoi` like functions: spaces, a sign,
+    /// digits and maybe some garbage after
+    pub fn int_string(&mut self) -> Vec<u8> {
+        let mut data = vec![];
+        for _ in 0..self.below(4) {
+            data.push(*self.choose(b" \t\n\x0b\x0c\r"));
+        }
+        match self.below(4) {
+            0 => data.push(b'-'),
+            1 => data.push(b'+'),
+            _ => {}
+        }
+        // leading zeros
+        let zeros = self.below(4) as usize;
+        data.resize(data.len() + zeros, b'0');
+        // enough digits to overflow sometimes
+        for _ in 0..self.below(22) {
+            data.push(b'0' + self.below(10) as u8);
+        }
+        if self.below(4) == 0 {
+            data.extend(self.c_string(4));
+        } else {
+            data.push(0);
+        }
+        data
+    }
+
+    /// a float, with `exp_len` bits of exponent and `mant_len` bits of
+    /// mantissa, with a lot of edge cases
+    fn float_bits(&mut self, exp_len: u32, mant_len: u32) -> u64 {
+        let bias = (1 << (exp_len - 1)) - 1;
+        let exp_max = (1 << exp_len) - 1;
+        let sign = self.next_u64() & 1;
+        let mant = self.next_u64() & ((1 << mant_len) - 1);
+        let (exp, mant) = match self.below(10) {
+            // zero
+            0 => (0, 0),
+            // subnormal
+            1 => (0, mant.max(1)),
+            // infinity
+            2 => (exp_max, 0),
+            // NaN, with any payload, quiet or not
+            3 => (exp_max, mant.max(1)),
+            // halfway between two integers, the bit for 0.5 is set and the
+            // bits after it are zero
+            4 | 5 => {
+                let exp = self.below(mant_len.into());
+                let half = 1 << (u64::from(mant_len) - exp - 1);
+                (bias + exp, (mant & !(half * 2 - 1)) | half)
+            }
+            // huge, every value is an integer
+            6 => {
+                let exp = bias + u64::from(mant_len) + self.below(8) - 4;
+                (exp.min(exp_max - 1), mant)
+            }
+            // the smallest and biggest normals
+            7 => (*self.choose(&[1, exp_max - 1]), mant),
+            // close to 1
+            8 => (bias - 2 + self.below(5), mant),
+            _ => (1 + self.below(exp_max - 1), mant),
+        };
+        (sign << (exp_len + mant_len)) | (exp << mant_len) | mant
+    }
+
+    pub fn f64(&mut self) -> f64 {
+        f64::from_bits(self.float_bits(11, 52))
+    }
+
+    pub fn f32(&mut self) -> f32 {
+        f32::from_bits(self.float_bits(8, 23) as u32)
+    }
+}
@@ -0,0 +1,438 @@
+//! Results of every test case, and the summary printed at the end.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::random;
+use crate::vm::{CallStats, Return, Vm};
+
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Pass,
+    /// the function returned something unexpected
+    Fail {
+        expected: String,
+        actual: String,
+    },
+    /// the case could not be executed, eg: the vm crashed
+    Error(String),
+}
+
+impl Outcome {
+    pub fn is_pass(&self) -> bool {
+        matches!(self, Outcome::Pass)
+    }
+}
+
+/// compare the expected and actual values of a case
+pub fn check<T: Debug + PartialEq>(expected: T, actual: T) -> Outcome {
+    if expected == actual {
+        return Outcome::Pass;
+    }
+    Outcome::Fail {
+        expected: format!("{:?}", expected),
+        actual: format!("{:?}", actual),
+    }
+}
+
+/// how the floats returned by a function are compared
+#[derive(Clone, Copy, Debug)]
+pub enum FloatCheck {
+    /// the same bits, so the sign of the zeros and the NaN payloads are
+    /// checked
+    Bitwise,
+    /// the same bits, but any NaN is equal to any other NaN, for the
+    /// references computed on the host, the NaNs it makes don't have the
+    /// sign and payload of every arch
+    AnyNan,
+    /// at most this number of ULPs apart, for the functions that are not
+    /// correctly rounded. The zeros and infinities still need the same sign,
+    /// and any NaN is equal to any other NaN, like [`FloatCheck::AnyNan`]
+    Ulps(u64),
+}
+
+impl FloatCheck {
+    pub fn matches(&self, expected: &Return, actual: &Return) -> bool {
+        match self {
+            FloatCheck::Bitwise => expected.same_bits(actual),
+            FloatCheck::AnyNan => expected.same_as(actual),
+            _ if expected.same_as(actual) => true,
+            // a distance of 0 is a zero with the other sign
+            FloatCheck::Ulps(max) => match expected.ulps(actual) {
+                Some(ulps) => ulps != 0 && ulps <= *max,
+                None => false,
+            },
+        }
+    }
+}
+
+/// same as [`check`], but the values are compared with the [`FloatCheck`],
+/// a failure show how many ULPs apart they are
+pub fn check_float(
+    float_check: FloatCheck,
+    expected: Return,
+    actual: Return,
+) -> Outcome {
+    if float_check.matches(&expected, &actual) {
+        return Outcome::Pass;
+    }
+    let actual = match expected.ulps(&actual) {
+        Some(ulps) => format!("{:?} ({} ulps)", actual, ulps),
+        None => format!("{:?}", actual),
+    };
+    Outcome::Fail {
+        expected: format!("{:?}", expected),
+        actual,
+    }
+}
+
+/// same as [`check_float`], with [`FloatCheck::AnyNan`]
+pub fn check_same(expected: Return, actual: Return) -> Outcome {
+    check_float(FloatCheck::AnyNan, expected, actual)
+}
+
+pub struct Case {
+    pub fn_sym: &'static str,
+    /// the kind of test and the index, eg: `static 3`
+    pub name: String,
+    pub outcome: Outcome,
+    /// more information about a failure, like the minimal failing input
+    pub notes: Vec<String>,
+    /// zero if the case was not executed with [`Report::run`]
+    pub time: Duration,
+    pub stats: CallStats,
+}
+
+/// all the cases executed on one arch
+pub struct Report {
+    pub arch: String,
+    pub cases: Vec<Case>,
+}
+
+impl Report {
+    pub fn new(arch: &str) -> Self {
+        Self {
+            arch: arch.to_string(),
+            cases: vec![],
+        }
+    }
+
+    /// add the result of a case, an error is also a result
+    pub fn record(
+        &mut self,
+        fn_sym: &'static str,
+        name: impl Display,
+        outcome: Result<Outcome>,
+    ) -> &Outcome {
+        let outcome = outcome.unwrap_or_else(|error| {
+            // the vm may be in any state after an error
+            Outcome::Error(format!("{:#}", error))
+        });
+        self.cases.push(Case {
+            fn_sym,
+            name: name.to_string(),
+            outcome,
+            notes: vec![],
+            time: Duration::ZERO,
+            stats: CallStats::default(),
+        });
+        &self.cases.last().unwrap().outcome
+    }
+
+    /// execute and record a case, keeping the time and what the emulator did,
+    /// returns true if the case passed
+    pub fn run<V: Vm>(
+        &mut self,
+        fn_sym: &'static str,
+        name: impl Display,
+        vm: &mut V,
+        case: impl FnOnce(&mut V) -> Result<Outcome>,
+    ) -> bool {
+        // ignore the calls made by the previous cases
+        vm.take_stats();
+        let start = Instant::now();
+        let outcome = case(vm);
+        let time = start.elapsed();
+        let passed = self.record(fn_sym, name, outcome).is_pass();
+        let case = self.cases.last_mut().unwrap();
+        case.time = time;
+        case.stats = vm.take_stats();
+        passed
+    }
+
+    /// the addr of a symbol, if it's missing it's recorded as an error, so
+    /// only the tests of that function are skipped
+    pub fn lookup(
+        &mut self,
+        vm: &mut impl Vm,
+        symbol: &'static str,
+    ) -> Option<u64> {
+        match vm.lookup_symbol(symbol) {
+            Ok(addr) => Some(addr),
+            Err(error) => {
+                self.record(symbol, "lookup", Err(error));
+                None
+            }
+        }
+    }
+
+    /// add more information to the last case
+    pub fn note(&mut self, note: impl Display) {
+        let case = self.cases.last_mut().expect("no case recorded");
+        case.notes.push(note.to_string());
+    }
+
+    pub fn success(&self) -> bool {
+        self.cases.iter().all(|case| case.outcome.is_pass())
+    }
+
+    /// the number of (pass, fail, error) cases of each function
+    pub fn counts(&self) -> BTreeMap<&'static str, (usize, usize, usize)> {
+        let mut counts = BTreeMap::new();
+        for case in &self.cases {
+            let count: &mut (usize, usize, usize) =
+                counts.entry(case.fn_sym).or_default();
+            match case.outcome {
+                Outcome::Pass => count.0 += 1,
+                Outcome::Fail { .. } => count.1 += 1,
+                Outcome::Error(_) => count.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// all the cases, for other tools to consume
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write!(out, "{{\"arch\":{},", json_str(&self.arch)).unwrap();
+        // a string, a JSON number would lose the low bits of the seed
+        let seed = format!("0x{:016x}", random::seed());
+        write!(out, "\"seed\":{},\"cases\":[", json_str(&seed)).unwrap();
+        for (i, case) in self.cases.iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            let (status, expected, actual, error) = match &case.outcome {
+                Outcome::Pass => ("pass", None, None, None),
+                Outcome::Fail { expected, actual } => {
+                    ("fail", Some(expected), Some(actual), None)
+                }
+                Outcome::Error(error) => ("error", None, None, Some(error)),
+            };
+            let opt = |value: Option<&String>| {
+                value.map(|value| json_str(value)).unwrap_or("null".into())
+            };
+            write!(
+                out,
+                "{{\"function\":{},\"name\":{},\"status\":\"{}\",",
+                json_str(case.fn_sym),
+                json_str(&case.name),
+                status
+            )
+            .unwrap();
+            write!(
+                out,
+                "\"expected\":{},\"actual\":{},\"error\":{},",
+                opt(expected),
+                opt(actual),
+                opt(error)
+            )
+            .unwrap();
+            let notes: Vec<_> =
+                case.notes.iter().map(|n| json_str(n)).collect();
+            write!(
+                out,
+                "\"notes\":[{}],\"calls\":{},\"instructions\":{},",
+                notes.join(","),
+                case.stats.calls,
+                case.stats.instructions
+            )
+            .unwrap();
+            write!(
+                out,
+                "\"exit\":{},\"time_us\":{}}}",
+                opt(case.stats.exit.as_ref()),
+                case.time.as_micros()
+            )
+            .unwrap();
+        }
+        out.push_str("]}\n");
+        out
+    }
+
+    /// all the cases in the JUnit format, one testsuite for each function
+    pub fn to_junit(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        writeln!(out, "<testsuites name=\"{}\">", xml_str(&self.arch)).unwrap();
+        let counts = self.counts();
+        for (fn_sym, (pass, fail, error)) in counts {
+            let cases: Vec<_> =
+                self.cases.iter().filter(|c| c.fn_sym == fn_sym).collect();
+            let time: Duration = cases.iter().map(|c| c.time).sum();
+            writeln!(
+                out,
+                "  <testsuite name=\"{}.{}\" tests=\"{}\" failures=\"{}\" \
+                errors=\"{}\" time=\"{:.6}\">",
+                xml_str(&self.arch),
+                xml_str(fn_sym),
+                pass + fail + error,
+                fail,
+                error,
+                time.as_secs_f64()
+            )
+            .unwrap();
+            for case in cases {
+                write!(
+                    out,
+                    "    <testcase classname=\"{}.{}\" name=\"{}\" \
+                    time=\"{:.6}\">",
+                    xml_str(&self.arch),
+                    xml_str(fn_sym),
+                    xml_str(&case.name),
+                    case.time.as_secs_f64()
+                )
+                .unwrap();
+                match &case.outcome {
+                    Outcome::Pass => {}
+                    Outcome::Fail { expected, actual } => write!(
+                        out,
+                        "<failure message=\"expected {} actual {}\"/>",
+                        xml_str(expected),
+                        xml_str(actual)
+                    )
+                    .unwrap(),
+                    Outcome::Error(error) => {
+                        write!(out, "<error message=\"{}\"/>", xml_str(error))
+                            .unwrap()
+                    }
+                }
+                let mut system_out = case.notes.clone();
+                system_out.push(format!(
+                    "calls {} instructions {} exit {}",
+                    case.stats.calls,
+                    case.stats.instructions,
+                    case.stats.exit.as_deref().unwrap_or("none")
+                ));
+                write!(
+                    out,
+                    "<system-out>{}</system-out>",
+                    xml_str(&system_out.join("\n"))
+                )
+                .unwrap();
+                out.push_str("</testcase>\n");
+            }
+            out.push_str("  </testsuite>\n");
+        }
+        out.push_str("</testsuites>\n");
+        out
+    }
+
+    /// write `<arch>.json` and `<arch>.xml` to `PINGU_REPORT_DIR`, or to
+    /// `target/pingu-reports` if not set
+    pub fn write(&self) -> Result<()> {
+        let dir = std::env::var_os("PINGU_REPORT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("target")
+                    .join("pingu-reports")
+            });
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("unable to create {}", dir.display()))?;
+        for (ext, data) in [("json", self.to_json()), ("xml", self.to_junit())]
+        {
+            let file = dir.join(format!("{}.{}", self.arch, ext));
+            std::fs::write(&file, data).with_context(|| {
+                format!("unable to write {}", file.display())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// a quoted and escaped JSON string
+fn json_str(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// escaped text for XML attributes and elements, control chars are not
+/// allowed in XML 1.0, so they are replaced
+fn xml_str(value: &str) -> String {
+    let mut out = String::new();
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' => out.push_str("&#10;"),
+            '\t' => out.push_str("&#9;"),
+            c if (c as u32) < 0x20 => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// the cases that didn't pass, followed by a table with the number of cases
+/// of each function and the random seed
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for case in &self.cases {
+            match &case.outcome {
+                Outcome::Pass => {}
+                Outcome::Fail { expected, actual } => writeln!(
+                    f,
+                    "{} {} {} FAIL expected {} actual {}",
+                    self.arch, case.fn_sym, case.name, expected, actual
+                )?,
+                Outcome::Error(error) => writeln!(
+                    f,
+                    "{} {} {} ERROR {}",
+                    self.arch, case.fn_sym, case.name, error
+                )?,
+            }
+            for note in &case.notes {
+                writeln!(f, "    {}", note)?;
+            }
+        }
+        writeln!(
+            f,
+            "{:<20} {:>6} {:>6} {:>6}",
+            self.arch, "pass", "fail", "error"
+        )?;
+        let mut total = (0, 0, 0);
+        for (fn_sym, (pass, fail, error)) in self.counts() {
+            writeln!(f, "{:<20} {:>6} {:>6} {:>6}", fn_sym, pass, fail, error)?;
+            total = (total.0 + pass, total.1 + fail, total.2 + error);
+        }
+        writeln!(
+            f,
+            "{:<20} {:>6} {:>6} {:>6}",
+            "total", total.0, total.1, total.2
+        )?;
+        // any random case can be replayed with the seed
+        write!(f, "random seed 0x{:016x}", random::seed())
+    }
+}
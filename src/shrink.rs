@@ -0,0 +1,169 @@
+//! Reduce a failing input to a minimal one that still fails.
+
+use std::fmt::{Debug, Display};
+
+use anyhow::Result;
+
+use crate::report::FloatCheck;
+use crate::vm::Return;
+
+/// max number of simplifications, just in case
+const MAX_STEPS: usize = 1000;
+
+pub trait Shrink: Clone + Debug {
+    /// simpler versions of the value, the simplest first. They need to be
+    /// strictly simpler, so the shrinking always ends.
+    fn candidates(&self) -> Vec<Self>;
+}
+
+/// removes chunks of bytes, then zero or simplify the bytes
+impl Shrink for Vec<u8> {
+    fn candidates(&self) -> Vec<Self> {
+        let mut candidates = vec![];
+        if !self.is_empty() {
+            candidates.push(vec![]);
+        }
+        let mut chunk = self.len() / 2;
+        while chunk > 0 {
+            for start in (0..self.len()).step_by(chunk) {
+                let end = (start + chunk).min(self.len());
+                candidates.push([&self[..start], &self[end..]].concat());
+            }
+            chunk /= 2;
+        }
+        for (i, byte) in self.iter().enumerate() {
+            for simpler in [0, 1, b'a'] {
+                if simpler < *byte {
+                    let mut candidate = self.clone();
+                    candidate[i] = simpler;
+                    candidates.push(candidate);
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// the complexity of a float: sign, the distance from 1.0 and mantissa bits
+/// set, zero is the simplest
+fn float_complexity(
+    zero: bool,
+    sign: bool,
+    exp: i64,
+    mant: u64,
+) -> (bool, i64, u32) {
+    if zero {
+        return (sign, 0, 0);
+    }
+    (sign, exp.abs() + 1, mant.count_ones())
+}
+
+macro_rules! impl_shrink_float {
+    ($ty:ty, $bits:ty, $mant_len:literal, $bias:literal) => {
+        impl Shrink for $ty {
+            fn candidates(&self) -> Vec<Self> {
+                let complexity = |value: $ty| {
+                    let bits = value.to_bits();
+                    let sign = value.is_sign_negative();
+                    let exp = ((bits << 1) >> ($mant_len + 1)) as i64;
+                    let mant = (bits & ((1 << $mant_len) - 1)) as u64;
+                    float_complexity(value == 0.0, sign, exp - $bias, mant)
+                };
+                let mut candidates = vec![0.0, 1.0, self.abs(), self.trunc()];
+                // remove the lower bits of the mantissa
+                for cleared in [$mant_len, $mant_len / 2, 8, 4, 1] {
+                    let mask = !((1 as $bits << cleared) - 1);
+                    candidates.push(<$ty>::from_bits(self.to_bits() & mask));
+                }
+                // closer to 1.0, also keeping the fraction
+                candidates.push(self / 2.0);
+                candidates.push(self * 2.0);
+                candidates.push((self.trunc() / 2.0).trunc() + self.fract());
+                let current = complexity(*self);
+                candidates.retain(|x| complexity(*x) < current);
+                candidates
+            }
+        }
+    };
+}
+impl_shrink_float!(f64, u64, 52, 1023);
+impl_shrink_float!(f32, u32, 23, 127);
+
+/// shrink the first value, then the second
+impl<A: Shrink, B: Shrink> Shrink for (A, B) {
+    fn candidates(&self) -> Vec<Self> {
+        let first = self.0.candidates().into_iter();
+        let second = self.1.candidates().into_iter();
+        first
+            .map(|a| (a, self.1.clone()))
+            .chain(second.map(|b| (self.0.clone(), b)))
+            .collect()
+    }
+}
+
+/// the minimal failing input, with the expected and actual values
+pub struct Failure<T> {
+    pub input: T,
+    pub expected: Return,
+    /// the function may also fail to execute
+    pub actual: Result<Return, String>,
+}
+
+impl<T: Debug> Display for Failure<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "minimal input {:02x?}", self.input)?;
+        write!(f, " expected {:?}", self.expected)?;
+        match &self.actual {
+            Ok(actual) => {
+                write!(f, " actual {:?}", actual)?;
+                match self.expected.ulps(actual) {
+                    Some(ulps) => write!(f, " ({} ulps)", ulps),
+                    None => Ok(()),
+                }
+            }
+            Err(error) => write!(f, " actual error({})", error),
+        }
+    }
+}
+
+/// simplify the input, while the function still returns something different
+/// from the expected value, returns None if the input don't fail
+pub fn shrink<T: Shrink>(
+    input: T,
+    expected: impl Fn(&T) -> Return,
+    actual: impl FnMut(&T) -> Result<Return>,
+) -> Option<Failure<T>> {
+    shrink_with(input, FloatCheck::Bitwise, expected, actual)
+}
+
+/// same as [`shrink`], but the floats are compared with the [`FloatCheck`]
+pub fn shrink_with<T: Shrink>(
+    input: T,
+    float_check: FloatCheck,
+    expected: impl Fn(&T) -> Return,
+    mut actual: impl FnMut(&T) -> Result<Return>,
+) -> Option<Failure<T>> {
+    let mut check = |input: T| {
+        let expected = expected(&input);
+        let actual = actual(&input).map_err(|error| error.to_string());
+        match &actual {
+            Ok(actual) if float_check.matches(&expected, actual) => None,
+            _ => Some(Failure {
+                input,
+                expected,
+                actual,
+            }),
+        }
+    };
+    let mut failure = check(input)?;
+    'steps: for _ in 0..MAX_STEPS {
+        for candidate in failure.input.candidates() {
+            if let Some(simpler) = check(candidate) {
+                failure = simpler;
+                continue 'steps;
+            }
+        }
+        break;
+    }
+    Some(failure)
+}
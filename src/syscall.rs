@@ -0,0 +1,239 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+
+use anyhow::Result;
+use icicle_mem::perm;
+use icicle_vm::cpu::debug_info::SourceLocation;
+use icicle_vm::cpu::{Cpu, Environment, ExceptionCode};
+use icicle_vm::VmExit;
+use pcode::VarNode;
+
+use crate::helper;
+
+/// how the arch pass the syscall number, params and return value
+pub struct SyscallAbi {
+    pub nr: VarNode,
+    pub args: [VarNode; 6],
+    pub ret: VarNode,
+    /// the number, name and number of params of the known syscalls
+    pub table: &'static [(u64, &'static str, usize)],
+}
+
+/// a syscall made by the guest
+pub struct SyscallRecord {
+    pub nr: u64,
+    pub name: Option<&'static str>,
+    pub args: Vec<u64>,
+    pub ret: u64,
+}
+
+impl fmt::Display for SyscallRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{}(", name)?,
+            None => write!(f, "syscall_{}(", self.nr)?,
+        }
+        for (i, arg) in self.args.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "0x{:x}", arg)?;
+        }
+        write!(f, ") = {}", self.ret as i64)
+    }
+}
+
+/// how the fake kernel answer a syscall
+pub enum Script {
+    /// return this value, negative values are the errno
+    Return(i64),
+    /// call the Fn with the syscall params and return the result
+    Handler(Box<dyn FnMut(&mut Cpu, &[u64]) -> i64>),
+}
+
+/// the syscalls not handled here are forwarded to the icicle env
+pub struct FakeKernel {
+    abi: SyscallAbi,
+    /// all the syscalls made, since the last restore
+    pub log: Vec<SyscallRecord>,
+    /// the data written into each fd, since the last restore
+    pub fds: HashMap<u64, Vec<u8>>,
+    /// answers for the next syscalls, by name
+    scripts: HashMap<&'static str, VecDeque<Script>>,
+}
+
+impl FakeKernel {
+    pub fn new(abi: SyscallAbi) -> Self {
+        Self {
+            abi,
+            log: vec![],
+            fds: HashMap::new(),
+            scripts: HashMap::new(),
+        }
+    }
+
+    /// answer the next call to the syscall `name` using `script`
+    pub fn script(&mut self, name: &'static str, script: Script) {
+        self.scripts.entry(name).or_default().push_back(script);
+    }
+
+    /// forget the log and the data written, but keep the scripts
+    pub fn clear(&mut self) {
+        self.log.clear();
+        self.fds.clear();
+    }
+
+    fn name(&self, nr: u64) -> Option<(&'static str, usize)> {
+        self.abi
+            .table
+            .iter()
+            .find(|(table_nr, _, _)| *table_nr == nr)
+            .map(|(_, name, args)| (*name, *args))
+    }
+
+    /// capture the data written by `write` and `writev`
+    fn write(
+        &mut self,
+        cpu: &mut Cpu,
+        name: &str,
+        args: &[u64],
+    ) -> Result<u64> {
+        let fd = args[0];
+        let iov = match name {
+            "write" => vec![(args[1], args[2])],
+            "writev" => {
+                let ptr_size = helper::ptr_size(cpu);
+                (0..args[2])
+                    .map(|i| {
+                        let iov = args[1] + (i * ptr_size * 2);
+                        let base = helper::read_uint(cpu, iov, ptr_size)?;
+                        let len =
+                            helper::read_uint(cpu, iov + ptr_size, ptr_size)?;
+                        Ok((base, len))
+                    })
+                    .collect::<Result<_>>()?
+            }
+            _ => unreachable!(),
+        };
+        let mut written = 0;
+        for (base, len) in iov {
+            let mut data = vec![0; len.try_into().unwrap()];
+            cpu.mem.read_bytes(base, &mut data, perm::NONE)?;
+            self.fds.entry(fd).or_default().extend(data);
+            written += len;
+        }
+        Ok(written)
+    }
+
+    /// returns the syscall result, or None if the icicle env should handle it
+    fn handle(&mut self, cpu: &mut Cpu) -> Option<u64> {
+        let nr = cpu.read_reg(self.abi.nr);
+        let (name, args_len) = match self.name(nr) {
+            Some((name, args_len)) => (Some(name), args_len),
+            None => (None, 6),
+        };
+        let args: Vec<u64> = self.abi.args[..args_len]
+            .iter()
+            .map(|reg| cpu.read_reg(*reg))
+            .collect();
+        let script = name
+            .and_then(|name| self.scripts.get_mut(name))
+            .and_then(|scripts| scripts.pop_front());
+        let ret = match (script, name) {
+            (Some(Script::Return(value)), _) => Some(value as u64),
+            (Some(Script::Handler(mut handler)), _) => {
+                Some(handler(cpu, &args) as u64)
+            }
+            (None, Some(name @ ("write" | "writev"))) => {
+                // EFAULT on invalid buffers
+                Some(self.write(cpu, name, &args).unwrap_or(-14i64 as u64))
+            }
+            (None, _) => None,
+        };
+        self.log.push(SyscallRecord {
+            nr,
+            name,
+            args,
+            ret: ret.unwrap_or_else(|| cpu.read_reg(self.abi.ret)),
+        });
+        ret
+    }
+}
+
+/// wraps the icicle env, to intercept the syscalls before it
+pub struct SyscallInterceptor {
+    inner: Box<dyn Environment>,
+    kernel: Rc<RefCell<FakeKernel>>,
+}
+
+impl Environment for SyscallInterceptor {
+    fn load(&mut self, cpu: &mut Cpu, path: &[u8]) -> Result<(), String> {
+        self.inner.load(cpu, path)
+    }
+
+    fn handle_exception(&mut self, cpu: &mut Cpu) -> Option<VmExit> {
+        if cpu.exception.code != ExceptionCode::Syscall as u32 {
+            return self.inner.handle_exception(cpu);
+        }
+        let ret = self.kernel.borrow_mut().handle(cpu);
+        let ret_reg = self.kernel.borrow().abi.ret;
+        match ret {
+            Some(ret) => {
+                cpu.write_reg(ret_reg, ret);
+                cpu.exception.clear();
+                None
+            }
+            None => {
+                let exit = self.inner.handle_exception(cpu);
+                // log the value returned by the icicle env
+                let ret = cpu.read_reg(ret_reg);
+                let mut kernel = self.kernel.borrow_mut();
+                if let Some(record) = kernel.log.last_mut() {
+                    record.ret = ret;
+                }
+                exit
+            }
+        }
+    }
+
+    fn next_timer(&self) -> u64 {
+        self.inner.next_timer()
+    }
+
+    fn symbolize_addr(
+        &mut self,
+        cpu: &mut Cpu,
+        addr: u64,
+    ) -> Option<SourceLocation> {
+        self.inner.symbolize_addr(cpu, addr)
+    }
+
+    fn lookup_symbol(&mut self, symbol: &str) -> Option<u64> {
+        self.inner.lookup_symbol(symbol)
+    }
+
+    fn snapshot(&mut self) -> Box<dyn Any> {
+        self.inner.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &Box<dyn Any>) {
+        self.inner.restore(snapshot)
+    }
+}
+
+/// create the icicle env, wrapped so the syscalls are intercepted by the
+/// returned [`FakeKernel`]
+pub fn intercept(
+    vm: &mut icicle_vm::Vm,
+    abi: SyscallAbi,
+) -> Result<Rc<RefCell<FakeKernel>>> {
+    let kernel = Rc::new(RefCell::new(FakeKernel::new(abi)));
+    vm.env = Box::new(SyscallInterceptor {
+        inner: icicle_vm::env::build_auto(vm)?,
+        kernel: Rc::clone(&kernel),
+    });
+    Ok(kernel)
+}
@@ -1,23 +1,35 @@
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::shrink::{self, Failure};
 use crate::vm::{Param, Return, Vm};
 use anyhow::Result;
 
 pub struct TestStatic {
-    param: String,
+    param: Vec<u8>,
     result: i64,
 }
 
 impl TestStatic {
-    fn test_on_vm(
+    fn call(
         &self,
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
-        let mut params = [Param::HeapData(self.param.as_bytes())];
+    ) -> Result<i64> {
+        let mut params = [Param::HeapData(&self.param)];
         let mut output = [Return::I64(0)];
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::I64(output)] = output else { unreachable!() };
-        Ok(output == self.result)
+        Ok(output)
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        Ok(check(self.result, self.call(fun_addr, ret_addr, vm)?))
     }
 }
 
@@ -35,20 +47,90 @@ pub const TESTS_STATIC: &[&str] = &[
     "-9223372036854775807",
     "-9223372036854775808",
 ];
-pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
+
+/// the musl implementation, the value wraps on overflow
+fn atoll(data: &[u8]) -> i64 {
+    let mut data = data
+        .iter()
+        .skip_while(|c| b" \t\n\x0b\x0c\r".contains(c))
+        .peekable();
+    let neg = data.peek() == Some(&&b'-');
+    if matches!(data.peek(), Some(b'-' | b'+')) {
+        data.next();
+    }
+    // calculated as a negative number, so i64::MIN don't overflow
+    let value = data.take_while(|c| c.is_ascii_digit()).fold(0i64, |n, c| {
+        n.wrapping_mul(10).wrapping_sub(i64::from(c - b'0'))
+    });
+    if neg {
+        value
+    } else {
+        value.wrapping_neg()
+    }
+}
+
+/// find the smallest string that still fails
+pub fn minimal_failure(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    param: &[u8],
+) -> Option<Failure<Vec<u8>>> {
+    let expected = |param: &Vec<u8>| Return::I64(atoll(param));
+    shrink::shrink(param.to_vec(), expected, |param| {
+        // the string always ends, even if all the NULs are removed
+        let mut param = param.clone();
+        param.push(0);
+        let test = TestStatic { param, result: 0 };
+        Ok(Return::I64(test.call(fun_addr, ret_addr, vm)?))
+    })
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
     const FN_SYM: &str = "atoll";
-    let fun_addr = vm.lookup_symbol(FN_SYM);
-    let ret_addr = vm.lookup_symbol("_dlstart");
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
 
     let tests_static = TESTS_STATIC.into_iter().map(|value| TestStatic {
-        param: format!("{}\x00", value),
+        param: format!("{}\x00", value).into_bytes(),
         result: i64::from_str_radix(value, 10).unwrap(),
     });
     for (i, test) in tests_static.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test static {} i64({})", FN_SYM, i, test.result);
-            return Ok(false);
+        let name = format!("static {}", i);
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, &test.param)
+            {
+                report.note(failure);
+            }
+        }
+    }
+
+    // test random numbers, with spaces, signs and garbage after the digits
+    let mut rng = Rng::for_fn(FN_SYM);
+    for i in 0..random::cases() {
+        let param = rng.int_string();
+        let result = atoll(&param);
+        let test = TestStatic { param, result };
+        let name = format!("random {} seed 0x{:016x}", i, random::seed());
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            let input = String::from_utf8_lossy(&test.param);
+            report.note(format_args!("input {:?}", input));
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, &test.param)
+            {
+                report.note(failure);
+            }
         }
     }
-    Ok(true)
 }
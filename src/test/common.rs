@@ -0,0 +1,44 @@
+//! Helpers shared by the test modules.
+
+use crate::report::Outcome;
+use crate::vm::Vm;
+use anyhow::Result;
+use icicle_mem::perm;
+
+/// the values of EINVAL and ERANGE on linux
+pub const EINVAL: i32 = 22;
+pub const ERANGE: i32 = 34;
+
+/// fills the buffers given to the functions, the bytes that must stay
+/// untouched keep it
+pub const SENTINEL: u8 = 0xa5;
+/// bytes around the space the function could use, that must stay untouched
+pub const GUARD: usize = 16;
+
+/// the string, up to the first NUL
+pub fn c_str(data: &[u8]) -> &[u8] {
+    let len = data.iter().position(|x| *x == 0).unwrap_or(data.len());
+    &data[..len]
+}
+
+/// read back the buffer the function wrote into, the memory is only restored
+/// on the next call
+pub fn read_buffer(vm: &mut impl Vm, addr: u64, len: usize) -> Result<Vec<u8>> {
+    let mut data = vec![0; len];
+    let mem = &mut vm.helper_mut().icicle.cpu.mem;
+    mem.read_bytes(addr, &mut data, perm::NONE)?;
+    Ok(data)
+}
+
+/// compare the buffers, showing only the bytes after the first difference
+pub fn check_buffer(expected: &[u8], actual: &[u8]) -> Outcome {
+    let Some(diff) = expected.iter().zip(actual).position(|(x, y)| x != y)
+    else {
+        return Outcome::Pass;
+    };
+    let end = (diff + 16).min(expected.len());
+    Outcome::Fail {
+        expected: format!("at {} {:02x?}", diff, &expected[diff..end]),
+        actual: format!("at {} {:02x?}", diff, &actual[diff..end]),
+    }
+}
@@ -0,0 +1,125 @@
+use crate::report::{check, check_same, Outcome, Report};
+use crate::test::common::ERANGE;
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+pub struct StrtolTest {
+    param: String,
+    result: i128,
+    errno: i32,
+}
+
+impl StrtolTest {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut params = [
+            Param::HeapData(self.param.as_bytes()),
+            // endptr
+            Param::Usize(0),
+            // base
+            Param::Usize(10),
+        ];
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // long have the size of a pointer
+        let long_bits = vm.helper().ptr_size() * 8;
+        let mask = u64::MAX >> (64 - long_bits);
+        let expected = (self.result as u64 & mask, self.errno);
+        Ok(check(expected, (output & mask, vm.errno()?)))
+    }
+}
+
+pub struct MathTest {
+    fn_sym: &'static str,
+    param: f64,
+}
+
+impl MathTest {
+    fn test_on_vm(&self, ret_addr: u64, vm: &mut impl Vm) -> Result<Outcome> {
+        let fun_addr = vm.lookup_symbol(self.fn_sym)?;
+        let mut params = [Param::F64(self.param)];
+        let mut output = [Return::F64(0.0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::F64(output)] = output else { unreachable!() };
+        let result = match self.fn_sym {
+            "log" => self.param.ln(),
+            "sqrt" => self.param.sqrt(),
+            _ => unreachable!(),
+        };
+        // musl math functions only report errors with the fenv exceptions
+        // (math_errhandling == MATH_ERREXCEPT), errno is never touched
+        let errno = vm.errno()?;
+        if errno != 0 {
+            return Ok(check(0, errno));
+        }
+        Ok(check_same(Return::F64(result), Return::F64(output)))
+    }
+}
+
+pub const TESTS_STRTOL: &[&str] = &[
+    "0",
+    "123",
+    "-123",
+    "2147483647",
+    "2147483648",
+    "-2147483648",
+    "-2147483649",
+    "9223372036854775807",
+    "9223372036854775808",
+    "-9223372036854775808",
+    "-9223372036854775809",
+    "99999999999999999999999",
+    "-99999999999999999999999",
+];
+pub const TESTS_MATH: &[(&str, f64)] = &[
+    ("log", -1.0),
+    ("log", 0.0),
+    ("log", f64::NEG_INFINITY),
+    ("sqrt", -1.0),
+    ("sqrt", -0.0),
+    ("sqrt", f64::NEG_INFINITY),
+];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "strtol";
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    // strtol saturate to the long limits, and set ERANGE
+    let long_bits = vm.helper().ptr_size() * 8;
+    let long_max = i128::from(u64::MAX >> (64 - long_bits + 1));
+    let long_min = -long_max - 1;
+    let tests_strtol = TESTS_STRTOL.iter().map(|value| {
+        let parsed = value.parse::<i128>().unwrap();
+        let result = parsed.clamp(long_min, long_max);
+        StrtolTest {
+            param: format!("{}\x00", value),
+            result,
+            errno: if result != parsed { ERANGE } else { 0 },
+        }
+    });
+    for (i, test) in tests_strtol.enumerate() {
+        let name =
+            format!("errno {} {:?}", i, test.param.trim_end_matches('\0'));
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+
+    let tests_math = TESTS_MATH.iter().map(|(fn_sym, param)| MathTest {
+        fn_sym,
+        param: *param,
+    });
+    for (i, test) in tests_math.enumerate() {
+        let name = format!("errno {} f64({})", i, test.param);
+        report.run(test.fn_sym, name, vm, |vm| test.test_on_vm(ret_addr, vm));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::report::{check, Outcome, Report};
+use crate::syscall::Script;
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// the value of EBADF on linux
+const EBADF: i32 = 9;
+
+pub struct GetpidTest {
+    pid: i64,
+}
+
+impl GetpidTest {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        vm.helper()
+            .kernel
+            .borrow_mut()
+            .script("getpid", Script::Return(self.pid));
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut [], &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        let kernel = vm.helper().kernel.borrow();
+        let logged: Vec<_> = kernel.log.iter().map(|call| call.name).collect();
+        // pid_t is an int
+        let expected = (vec![Some("getpid")], self.pid as u64 & 0xffff_ffff);
+        Ok(check(expected, (logged, output & 0xffff_ffff)))
+    }
+}
+
+pub struct WriteTest {
+    fd: u64,
+    data: &'static [u8],
+    /// the errno returned by the kernel, if any
+    error: Option<i32>,
+}
+
+impl WriteTest {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        if let Some(error) = self.error {
+            vm.helper()
+                .kernel
+                .borrow_mut()
+                .script("write", Script::Return(-i64::from(error)));
+        }
+        let mut params = [
+            Param::Usize(self.fd),
+            Param::HeapData(self.data),
+            Param::Usize(self.data.len() as u64),
+        ];
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        let written = vm.helper().output(self.fd);
+        // write returns a ssize_t
+        let mask = u64::MAX >> (64 - (vm.helper().ptr_size() * 8));
+        match self.error {
+            None => {
+                let expected = (self.data.len() as u64 & mask, self.data);
+                Ok(check(expected, (output & mask, written.as_slice())))
+            }
+            // musl return -1 and set the errno
+            Some(error) => {
+                let actual = (output & mask, written.len(), vm.errno()?);
+                Ok(check((mask, 0, error), actual))
+            }
+        }
+    }
+}
+
+pub const TESTS_GETPID: &[i64] = &[1, 1234, 0x7fff_ffff];
+pub const TESTS_WRITE: [(u64, &[u8], Option<i32>); 5] = [
+    (1, b"hello\n", None),
+    (2, b"\x00\x01\x02\xff", None),
+    (3, b"", None),
+    (1, b"fail", Some(EBADF)),
+    (1000, b"other fd", None),
+];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    const GETPID_SYM: &str = "getpid";
+    if let Some(fun_addr) = report.lookup(vm, GETPID_SYM) {
+        let tests_getpid =
+            TESTS_GETPID.iter().map(|pid| GetpidTest { pid: *pid });
+        for (i, test) in tests_getpid.enumerate() {
+            let name = format!("static {} pid({})", i, test.pid);
+            report.run(GETPID_SYM, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+        }
+    }
+
+    const WRITE_SYM: &str = "write";
+    let Some(fun_addr) = report.lookup(vm, WRITE_SYM) else {
+        return;
+    };
+    let tests_write = TESTS_WRITE
+        .into_iter()
+        .map(|(fd, data, error)| WriteTest { fd, data, error });
+    for (i, test) in tests_write.enumerate() {
+        let name = format!("static {} fd({})", i, test.fd);
+        report.run(WRITE_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+}
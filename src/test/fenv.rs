@@ -0,0 +1,253 @@
+//! The floating point environment of `fenv.h`. The values of the macros are
+//! the bits of the control and status registers, so they depend on the arch.
+//! The exception flags are checked in the same session as the operation that
+//! raised them.
+
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::{bail, Result};
+use target_lexicon::Architecture;
+
+/// the rounding modes of `fesetround`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rounding {
+    ToNearest,
+    Upward,
+    Downward,
+    TowardZero,
+}
+
+pub const ROUNDINGS: [Rounding; 4] = [
+    Rounding::ToNearest,
+    Rounding::Upward,
+    Rounding::Downward,
+    Rounding::TowardZero,
+];
+
+fn is_aarch64(vm: &impl Vm) -> bool {
+    let architecture = &vm.helper().icicle.cpu.arch.triple.architecture;
+    matches!(architecture, Architecture::Aarch64(_))
+}
+
+impl Rounding {
+    /// the value of the `FE_*` macro. The RC bits of the x87 control word,
+    /// also used for the MXCSR, or the RMode bits of the aarch64 FPCR
+    pub fn value(self, vm: &impl Vm) -> u64 {
+        let (upward, downward) = if is_aarch64(vm) {
+            (0x40_0000, 0x80_0000)
+        } else {
+            (0x800, 0x400)
+        };
+        match self {
+            Rounding::ToNearest => 0,
+            Rounding::Upward => upward,
+            Rounding::Downward => downward,
+            Rounding::TowardZero => upward | downward,
+        }
+    }
+
+    /// round to an integer in this mode
+    pub fn round(self, value: f64) -> f64 {
+        match self {
+            Rounding::ToNearest => value.round_ties_even(),
+            Rounding::Upward => value.ceil(),
+            Rounding::Downward => value.floor(),
+            Rounding::TowardZero => value.trunc(),
+        }
+    }
+}
+
+/// call `fesetround`, the mode is only kept for the next calls inside a
+/// session
+pub fn set_rounding(
+    vm: &mut impl Vm,
+    ret_addr: u64,
+    rounding: Rounding,
+) -> Result<()> {
+    let fun_addr = vm.lookup_symbol("fesetround")?;
+    let mut params = [Param::Usize(rounding.value(vm))];
+    let mut output = [Return::Usize(0)];
+    vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+    let [Return::Usize(output)] = output else { unreachable!() };
+    // returns an int, 0 on success
+    if output as u32 != 0 {
+        bail!("fesetround({:?}) failed", rounding);
+    }
+    Ok(())
+}
+
+/// the exception flags of `fetestexcept`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Except {
+    Invalid,
+    DivByZero,
+    Overflow,
+    Underflow,
+    Inexact,
+}
+
+/// also `FE_ALL_EXCEPT`, without the x86 denormal flag
+pub const EXCEPTS: [Except; 5] = [
+    Except::Invalid,
+    Except::DivByZero,
+    Except::Overflow,
+    Except::Underflow,
+    Except::Inexact,
+];
+
+impl Except {
+    /// the value of the `FE_*` macro. The bits of the x87 status word and the
+    /// MXCSR, or of the aarch64 FPSR
+    pub fn value(self, vm: &impl Vm) -> u64 {
+        let shift = if is_aarch64(vm) { 0 } else { 1 };
+        match self {
+            Except::Invalid => 1,
+            Except::DivByZero => 2 << shift,
+            Except::Overflow => 4 << shift,
+            Except::Underflow => 8 << shift,
+            Except::Inexact => 16 << shift,
+        }
+    }
+}
+
+fn excepts_value(excepts: &[Except], vm: &impl Vm) -> u64 {
+    excepts
+        .iter()
+        .fold(0, |value, except| value | except.value(vm))
+}
+
+/// the operation that raises the exceptions
+#[derive(Debug)]
+enum Op {
+    /// a libm function, with double params
+    Math(&'static str, &'static [f64]),
+    /// `feraiseexcept`
+    Raise(&'static [Except]),
+}
+
+/// the operations, with the exceptions IEEE 754 requires for them. Overflow
+/// and underflow are always inexact
+const TESTS_EXCEPT: &[(Op, &[Except])] = &[
+    (Op::Math("sqrt", &[4.0]), &[]),
+    (Op::Math("sqrt", &[2.0]), &[Except::Inexact]),
+    (Op::Math("sqrt", &[-1.0]), &[Except::Invalid]),
+    (Op::Math("log", &[0.0]), &[Except::DivByZero]),
+    (Op::Math("log", &[-1.0]), &[Except::Invalid]),
+    (Op::Math("pow", &[0.0, -1.0]), &[Except::DivByZero]),
+    (Op::Math("pow", &[2.0, 0.5]), &[Except::Inexact]),
+    (Op::Math("exp", &[0.0]), &[]),
+    (
+        Op::Math("exp", &[1000.0]),
+        &[Except::Overflow, Except::Inexact],
+    ),
+    (
+        Op::Math("exp", &[-1000.0]),
+        &[Except::Underflow, Except::Inexact],
+    ),
+    (Op::Math("rint", &[2.5]), &[Except::Inexact]),
+    (Op::Math("rint", &[2.0]), &[]),
+    // unlike rint, it never raise inexact
+    (Op::Math("nearbyint", &[2.5]), &[]),
+    (Op::Raise(&[]), &[]),
+    (Op::Raise(&[Except::Invalid]), &[Except::Invalid]),
+    (Op::Raise(&[Except::DivByZero]), &[Except::DivByZero]),
+    (Op::Raise(&[Except::Overflow]), &[Except::Overflow]),
+    (Op::Raise(&[Except::Underflow]), &[Except::Underflow]),
+    (Op::Raise(&[Except::Inexact]), &[Except::Inexact]),
+    (Op::Raise(&EXCEPTS), &EXCEPTS),
+];
+
+pub struct TestExcept {
+    op: &'static Op,
+    excepts: &'static [Except],
+}
+
+impl TestExcept {
+    fn fn_sym(&self) -> &'static str {
+        match self.op {
+            Op::Math(fn_sym, _) => fn_sym,
+            Op::Raise(_) => "feraiseexcept",
+        }
+    }
+
+    /// the exceptions raised by the op, and the ones left after
+    /// `feclearexcept`
+    fn call(
+        &self,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Vec<Except>, Vec<Except>)> {
+        // the flags are kept until the end of the session
+        vm.begin_session();
+        let result = self.run(ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Vec<Except>, Vec<Except>)> {
+        clear_excepts(vm, ret_addr)?;
+        let fun_addr = vm.lookup_symbol(self.fn_sym())?;
+        match self.op {
+            Op::Math(_, params) => {
+                let mut params: Vec<_> =
+                    params.iter().map(|param| Param::F64(*param)).collect();
+                let mut output = [Return::F64(0.0)];
+                vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+            }
+            Op::Raise(excepts) => {
+                let excepts = excepts_value(excepts, vm);
+                let mut params = [Param::Usize(excepts)];
+                let mut output = [Return::Usize(0)];
+                vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+            }
+        }
+        let raised = test_excepts(vm, ret_addr)?;
+        clear_excepts(vm, ret_addr)?;
+        Ok((raised, test_excepts(vm, ret_addr)?))
+    }
+
+    fn test_on_vm(&self, ret_addr: u64, vm: &mut impl Vm) -> Result<Outcome> {
+        let output = self.call(ret_addr, vm)?;
+        Ok(check((self.excepts.to_vec(), vec![]), output))
+    }
+}
+
+/// `feclearexcept(FE_ALL_EXCEPT)`
+fn clear_excepts(vm: &mut impl Vm, ret_addr: u64) -> Result<()> {
+    let fun_addr = vm.lookup_symbol("feclearexcept")?;
+    let mut params = [Param::Usize(excepts_value(&EXCEPTS, vm))];
+    let mut output = [Return::Usize(0)];
+    vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+    Ok(())
+}
+
+/// `fetestexcept(FE_ALL_EXCEPT)`, as a list
+fn test_excepts(vm: &mut impl Vm, ret_addr: u64) -> Result<Vec<Except>> {
+    let fun_addr = vm.lookup_symbol("fetestexcept")?;
+    let mut params = [Param::Usize(excepts_value(&EXCEPTS, vm))];
+    let mut output = [Return::Usize(0)];
+    vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+    let [Return::Usize(output)] = output else { unreachable!() };
+    let raised = EXCEPTS
+        .into_iter()
+        .filter(|except| output & except.value(vm) != 0);
+    Ok(raised.collect())
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    for (i, (op, excepts)) in TESTS_EXCEPT.iter().enumerate() {
+        let test = TestExcept { op, excepts };
+        let name = match op {
+            Op::Math(_, params) => format!("static {} f64({:?})", i, params),
+            Op::Raise(excepts) => format!("static {} {:?}", i, excepts),
+        };
+        report.run(test.fn_sym(), name, vm, |vm| test.test_on_vm(ret_addr, vm));
+    }
+}
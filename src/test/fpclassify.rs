@@ -0,0 +1,85 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Input, Return, Vm};
+use anyhow::Result;
+
+/// the classes of musl `math.h`
+const FP_NAN: u64 = 0;
+const FP_INFINITE: u64 = 1;
+const FP_ZERO: u64 = 2;
+const FP_SUBNORMAL: u64 = 3;
+const FP_NORMAL: u64 = 4;
+
+pub struct TestStatic {
+    input: Input,
+    result: u64,
+}
+
+impl TestStatic {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut [self.input.param()], &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // it returns an int
+        Ok(check(self.result, output & 0xffff_ffff))
+    }
+}
+
+/// values with a special classification, for `__fpclassify`
+pub const TESTS_STATIC: &[(f64, u64)] = &[
+    (0.0, FP_ZERO),
+    (-0.0, FP_ZERO),
+    (1.0, FP_NORMAL),
+    (f64::MIN_POSITIVE, FP_NORMAL),
+    (f64::MIN_POSITIVE / 2.0, FP_SUBNORMAL),
+    (f64::MAX, FP_NORMAL),
+    (f64::INFINITY, FP_INFINITE),
+    (f64::NEG_INFINITY, FP_INFINITE),
+    (f64::NAN, FP_NAN),
+];
+
+/// the same for `__fpclassifyf`
+pub const TESTS_STATIC_F32: &[(f32, u64)] = &[
+    (0.0, FP_ZERO),
+    (-0.0, FP_ZERO),
+    (1.0, FP_NORMAL),
+    (f32::MIN_POSITIVE, FP_NORMAL),
+    (f32::MIN_POSITIVE / 2.0, FP_SUBNORMAL),
+    (f32::MAX, FP_NORMAL),
+    (f32::INFINITY, FP_INFINITE),
+    (f32::NEG_INFINITY, FP_INFINITE),
+    (f32::NAN, FP_NAN),
+];
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    let tests_f64 = TESTS_STATIC.iter().map(|(value, result)| TestStatic {
+        input: Input::F64(*value),
+        result: *result,
+    });
+    let tests_f32 = TESTS_STATIC_F32.iter().map(|(value, result)| TestStatic {
+        input: Input::F32(*value),
+        result: *result,
+    });
+    let tests = [
+        ("__fpclassify", tests_f64.collect::<Vec<_>>()),
+        ("__fpclassifyf", tests_f32.collect()),
+    ];
+    for (fn_sym, tests) in tests {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        for (i, test) in tests.iter().enumerate() {
+            let name = format!("static {} {:?}", i, test.input);
+            report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+pub struct TestStatic {
+    name: Vec<u8>,
+    result: Option<Vec<u8>>,
+}
+
+impl TestStatic {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut params = [Param::HeapData(&self.name)];
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // NULL if the variable is missing
+        let value = if output == 0 {
+            None
+        } else {
+            let mut value = vec![];
+            vm.helper_mut()
+                .icicle
+                .cpu
+                .mem
+                .read_cstr(output, &mut value)?;
+            Some(value)
+        };
+        Ok(check(self.result.as_ref(), value.as_ref()))
+    }
+}
+
+/// names that are not in the environment, or only a prefix of one
+pub const TESTS_MISSING: &[&[u8]] =
+    &[b"", b"NOT_SET", b"HOM", b"HOME=", b"PINGU_", b"home"];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "getenv";
+    // getenv only works after musl is initialized
+    let Some(boot) = vm.helper().boot.clone() else {
+        return;
+    };
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    let tests_env = boot.env.iter().map(|var| {
+        let split = var.iter().position(|x| *x == b'=').unwrap();
+        TestStatic {
+            name: var[..split].iter().copied().chain([0]).collect(),
+            result: Some(var[split + 1..].to_vec()),
+        }
+    });
+    let tests_missing = TESTS_MISSING.iter().map(|name| TestStatic {
+        name: name.iter().copied().chain([0]).collect(),
+        result: None,
+    });
+    for (i, test) in tests_env.chain(tests_missing).enumerate() {
+        let name = String::from_utf8_lossy(&test.name);
+        let name = format!("static {} {:?}", i, name.trim_end_matches('\0'));
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+}
@@ -0,0 +1,67 @@
+use crate::arch::host::{self, Host};
+use crate::report::{check_float, Outcome, Report};
+use crate::test::{atoll, cos, rint, rintf, strlen};
+use crate::vm::{Input, Return, Vm};
+use anyhow::Result;
+
+/// compare the emulated result with the native one, no reference table is
+/// needed
+pub struct HostTest {
+    fn_sym: &'static str,
+    input: Input,
+    output: Return,
+}
+
+impl HostTest {
+    fn test_on_vm(&self, vm: &mut impl Vm, host: &mut Host) -> Result<Outcome> {
+        let fun_addr = vm.lookup_symbol(self.fn_sym)?;
+        let ret_addr = vm.lookup_symbol("_dlstart")?;
+        let mut emulated = [self.output.clone()];
+        vm.call(fun_addr, ret_addr, &mut [self.input.param()], &mut emulated)?;
+
+        let inputs = std::slice::from_ref(&self.input);
+        let native = host.call(self.fn_sym, inputs, &self.output)?;
+
+        let [emulated] = emulated;
+        let float_check = host::float_check(self.fn_sym);
+        Ok(check_float(float_check, native, emulated))
+    }
+}
+
+pub fn all_tests(vm: &mut impl Vm, host: &mut Host, report: &mut Report) {
+    let tests_strlen =
+        strlen::TESTS_STATIC.into_iter().map(|(data, _)| HostTest {
+            fn_sym: "strlen",
+            input: Input::Bytes(data.to_vec()),
+            output: Return::Usize(0),
+        });
+    let tests_atoll = atoll::TESTS_STATIC.iter().map(|value| HostTest {
+        fn_sym: "atoll",
+        input: Input::Bytes(format!("{}\x00", value).into_bytes()),
+        output: Return::I64(0),
+    });
+    let tests_f64 = ["sin", "cos", "rint"].into_iter().flat_map(|fn_sym| {
+        cos::TESTS_STATIC
+            .iter()
+            .chain(rint::TESTS_STATIC)
+            .map(move |value| HostTest {
+                fn_sym,
+                input: Input::F64(*value),
+                output: Return::F64(0.0),
+            })
+    });
+    let tests_f32 = rintf::TESTS_STATIC.iter().map(|value| HostTest {
+        fn_sym: "rintf",
+        input: Input::F32(*value),
+        output: Return::F32(0.0),
+    });
+    let tests = tests_strlen
+        .chain(tests_atoll)
+        .chain(tests_f64)
+        .chain(tests_f32)
+        .filter(|test| !host::diverges(test.fn_sym));
+    for (i, test) in tests.enumerate() {
+        let name = format!("host {} {:?}", i, test.input);
+        report.run(test.fn_sym, name, vm, |vm| test.test_on_vm(vm, host));
+    }
+}
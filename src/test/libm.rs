@@ -0,0 +1,488 @@
+//! A table of libm functions, with the `f` variants, compared with the Rust
+//! methods. The functions with an exact result are compared bitwise, the
+//! others with the ULPs of the host libm, that is not correctly rounded
+//! either.
+
+use std::cell::Cell;
+use std::ops::Rem;
+
+use crate::random::{self, Rng};
+use crate::report::{check, check_float, FloatCheck, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// the signature of the function, with the host reference
+#[derive(Clone, Copy)]
+enum Reference {
+    F64(fn(f64) -> f64),
+    F64F64(fn(f64, f64) -> f64),
+    F64F64F64(fn(f64, f64, f64) -> f64),
+    F32(fn(f32) -> f32),
+    F32F32(fn(f32, f32) -> f32),
+    F32F32F32(fn(f32, f32, f32) -> f32),
+    /// `ldexp`, the second param is an int
+    Ldexp(fn(f64, i32) -> f64),
+    Ldexpf(fn(f32, i32) -> f32),
+    /// `frexp`, the exponent is written to a pointer
+    Frexp(fn(f64) -> (f64, i32)),
+    Frexpf(fn(f32) -> (f32, i32)),
+}
+
+/// the result is exact, but the references make the NaNs of the host
+const EXACT: FloatCheck = FloatCheck::AnyNan;
+/// the host libm is within 1 ULP for most functions
+const CLOSE: FloatCheck = FloatCheck::Ulps(1);
+/// and within 2 ULPs for the ones that are harder to round
+const LOOSE: FloatCheck = FloatCheck::Ulps(2);
+
+const FUNCTIONS: &[(&str, Reference, FloatCheck)] = &[
+    ("exp", Reference::F64(f64::exp), CLOSE),
+    ("exp2", Reference::F64(f64::exp2), CLOSE),
+    ("expm1", Reference::F64(f64::exp_m1), CLOSE),
+    ("log", Reference::F64(f64::ln), CLOSE),
+    ("log2", Reference::F64(f64::log2), CLOSE),
+    ("log10", Reference::F64(f64::log10), LOOSE),
+    ("log1p", Reference::F64(f64::ln_1p), CLOSE),
+    ("pow", Reference::F64F64(f64::powf), CLOSE),
+    ("sqrt", Reference::F64(f64::sqrt), EXACT),
+    ("cbrt", Reference::F64(f64::cbrt), LOOSE),
+    ("hypot", Reference::F64F64(f64::hypot), CLOSE),
+    ("fmod", Reference::F64F64(f64::rem), EXACT),
+    ("remainder", Reference::F64F64(remainder), EXACT),
+    ("atan", Reference::F64(f64::atan), CLOSE),
+    ("atan2", Reference::F64F64(f64::atan2), CLOSE),
+    ("asin", Reference::F64(f64::asin), CLOSE),
+    ("acos", Reference::F64(f64::acos), CLOSE),
+    ("sinh", Reference::F64(f64::sinh), LOOSE),
+    ("cosh", Reference::F64(f64::cosh), LOOSE),
+    ("tanh", Reference::F64(f64::tanh), LOOSE),
+    ("floor", Reference::F64(f64::floor), EXACT),
+    ("ceil", Reference::F64(f64::ceil), EXACT),
+    ("trunc", Reference::F64(f64::trunc), EXACT),
+    ("round", Reference::F64(f64::round), EXACT),
+    ("fabs", Reference::F64(f64::abs), EXACT),
+    ("fma", Reference::F64F64F64(f64::mul_add), EXACT),
+    ("ldexp", Reference::Ldexp(ldexp), EXACT),
+    ("frexp", Reference::Frexp(frexp), EXACT),
+    ("expf", Reference::F32(f32::exp), CLOSE),
+    ("exp2f", Reference::F32(f32::exp2), CLOSE),
+    ("expm1f", Reference::F32(f32::exp_m1), CLOSE),
+    ("logf", Reference::F32(f32::ln), CLOSE),
+    ("log2f", Reference::F32(f32::log2), CLOSE),
+    ("log10f", Reference::F32(f32::log10), LOOSE),
+    ("log1pf", Reference::F32(f32::ln_1p), CLOSE),
+    ("powf", Reference::F32F32(f32::powf), CLOSE),
+    ("sqrtf", Reference::F32(f32::sqrt), EXACT),
+    ("cbrtf", Reference::F32(f32::cbrt), LOOSE),
+    ("hypotf", Reference::F32F32(f32::hypot), CLOSE),
+    ("fmodf", Reference::F32F32(f32::rem), EXACT),
+    ("remainderf", Reference::F32F32(remainderf), EXACT),
+    ("atanf", Reference::F32(f32::atan), CLOSE),
+    ("atan2f", Reference::F32F32(f32::atan2), CLOSE),
+    ("asinf", Reference::F32(f32::asin), CLOSE),
+    ("acosf", Reference::F32(f32::acos), CLOSE),
+    ("sinhf", Reference::F32(f32::sinh), LOOSE),
+    ("coshf", Reference::F32(f32::cosh), LOOSE),
+    ("tanhf", Reference::F32(f32::tanh), LOOSE),
+    ("floorf", Reference::F32(f32::floor), EXACT),
+    ("ceilf", Reference::F32(f32::ceil), EXACT),
+    ("truncf", Reference::F32(f32::trunc), EXACT),
+    ("roundf", Reference::F32(f32::round), EXACT),
+    ("fabsf", Reference::F32(f32::abs), EXACT),
+    ("fmaf", Reference::F32F32F32(f32::mul_add), EXACT),
+    ("ldexpf", Reference::Ldexpf(ldexpf), EXACT),
+    ("frexpf", Reference::Frexpf(frexpf), EXACT),
+];
+
+/// the edges of the domains, and the values that overflow or underflow
+pub const TESTS_UNARY: &[f64] = &[
+    0.0,
+    -0.0,
+    0.1,
+    0.5,
+    -0.5,
+    0.999999,
+    1.0,
+    -1.0,
+    1.5,
+    2.0,
+    -2.5,
+    10.0,
+    std::f64::consts::PI,
+    88.0,
+    89.0,
+    -104.0,
+    709.0,
+    710.0,
+    -745.0,
+    1.0e22,
+    1.0e300,
+    -1.0e300,
+    1.0e-300,
+    5.0e-324,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+
+/// every pair of them is tested
+pub const TESTS_BINARY: &[f64] = &[
+    0.0,
+    -0.0,
+    0.5,
+    1.0,
+    -1.0,
+    2.0,
+    -2.5,
+    3.0,
+    1.0e300,
+    1.0e-300,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+
+/// the product is only exact with the fused multiply add
+pub const TESTS_FMA: &[[f64; 3]] = &[
+    [0.1, 10.0, -1.0],
+    [1.0000000000000002, 0.9999999999999998, -1.0],
+    [1.0e308, 10.0, -1.0e308],
+    [5.0e-324, 0.5, 0.0],
+    [-0.0, 0.0, 0.0],
+    [0.0, 0.0, -0.0],
+    [f64::INFINITY, 0.0, 1.0],
+    [f64::INFINITY, 1.0, f64::NEG_INFINITY],
+    [3.0, 1.0 / 3.0, -1.0],
+];
+
+/// results checked by hand, with the exponent of `frexp`. They don't depend
+/// on the references, so an int or pointer passed in the wrong register is
+/// caught even if the reference is wrong too
+pub const TESTS_SANITY: &[(&str, &[f64], f64, Option<i64>)] = &[
+    ("ldexp", &[1.0, 3.0], 8.0, None),
+    ("ldexp", &[3.0, -1.0], 1.5, None),
+    ("ldexpf", &[1.0, 3.0], 8.0, None),
+    ("frexp", &[8.0], 0.5, Some(4)),
+    ("frexpf", &[8.0], 0.5, Some(4)),
+];
+
+/// the exponents of `ldexp`, the ones that reach the subnormals and the
+/// overflow
+pub const TESTS_LDEXP: &[i32] = &[
+    0, 1, -1, 127, 128, -149, -150, 1023, 1024, -1022, -1074, -1075, 5000,
+];
+
+/// the IEEE remainder, with the quotient rounded to even. Each step is exact
+fn remainder(x: f64, y: f64) -> f64 {
+    if x.is_nan() || y.is_nan() || x.is_infinite() || y == 0.0 {
+        return f64::NAN;
+    }
+    if y.is_infinite() {
+        return x;
+    }
+    let y = y.abs();
+    // in (-2y, 2y), the parity of the quotient is the one of the result
+    let r = if y < f64::MAX / 2.0 { x % (2.0 * y) } else { x };
+    let (sign, mut r) = (r.signum(), r.abs());
+    let odd = r >= y;
+    if odd {
+        r -= y;
+    }
+    let half = if y < f64::MAX / 2.0 {
+        r * 2.0 > y
+    } else {
+        r > y * 0.5
+    };
+    let tie = if y < f64::MAX / 2.0 {
+        r * 2.0 == y
+    } else {
+        r == y * 0.5
+    };
+    if half || (tie && odd) {
+        r -= y;
+    }
+    sign * r
+}
+
+/// the result is a float, so it's exact
+fn remainderf(x: f32, y: f32) -> f32 {
+    remainder(x.into(), y.into()) as f32
+}
+
+/// `x * 2^n`, like musl in two steps, the last one avoids rounding twice
+/// in the subnormals
+fn ldexp(x: f64, n: i32) -> f64 {
+    let exp2 = |n: i32| f64::from_bits(((0x3ff + n) as u64) << 52);
+    let (mut y, mut n) = (x, n);
+    for _ in 0..2 {
+        if n > 1023 {
+            y *= exp2(1023);
+            n -= 1023;
+        } else if n < -1022 {
+            y *= exp2(-1022 + 53);
+            n += 1022 - 53;
+        }
+    }
+    y * exp2(n.clamp(-1022, 1023))
+}
+
+/// the product is exact as a double, only the conversion rounds
+fn ldexpf(x: f32, n: i32) -> f32 {
+    ldexp(x.into(), n.clamp(-400, 400)) as f32
+}
+
+/// the mantissa in [0.5, 1) and the exponent, the exponent of zero is 0
+fn frexp(x: f64) -> (f64, i32) {
+    if x == 0.0 || !x.is_finite() {
+        return (x, 0);
+    }
+    let bits = x.to_bits();
+    let exp = ((bits >> 52) & 0x7ff) as i32;
+    if exp == 0 {
+        // a subnormal, normalize it first
+        let (mant, exp) = frexp(x * f64::from_bits((0x3ff + 64) << 52));
+        return (mant, exp - 64);
+    }
+    (
+        f64::from_bits((bits & !(0x7ff << 52)) | (0x3fe << 52)),
+        exp - 1022,
+    )
+}
+
+fn frexpf(x: f32) -> (f32, i32) {
+    let (mant, exp) = frexp(x.into());
+    (mant as f32, exp)
+}
+
+pub struct TestStatic {
+    fn_sym: &'static str,
+    reference: Reference,
+    float_check: FloatCheck,
+    /// the params as doubles, converted to the type of the function
+    params: Vec<f64>,
+}
+
+impl TestStatic {
+    /// the result, and the exponent of `frexp`. None when it's not written,
+    /// for inf and nan
+    fn expected(&self) -> (Return, Option<i64>) {
+        let p = &self.params;
+        // the params of the f32 functions are floats, so this is exact
+        let f = |i: usize| p[i] as f32;
+        // the mantissa is only finite when the param is
+        let exp = |mant: f64, exp: i32| mant.is_finite().then_some(exp);
+        match self.reference {
+            Reference::F64(fun) => (Return::F64(fun(p[0])), None),
+            Reference::F64F64(fun) => (Return::F64(fun(p[0], p[1])), None),
+            Reference::F64F64F64(fun) => {
+                (Return::F64(fun(p[0], p[1], p[2])), None)
+            }
+            Reference::F32(fun) => (Return::F32(fun(f(0))), None),
+            Reference::F32F32(fun) => (Return::F32(fun(f(0), f(1))), None),
+            Reference::F32F32F32(fun) => {
+                (Return::F32(fun(f(0), f(1), f(2))), None)
+            }
+            Reference::Ldexp(fun) => {
+                (Return::F64(fun(p[0], p[1] as i32)), None)
+            }
+            Reference::Ldexpf(fun) => {
+                (Return::F32(fun(f(0), p[1] as i32)), None)
+            }
+            Reference::Frexp(fun) => {
+                let (mant, value) = fun(p[0]);
+                (Return::F64(mant), exp(mant, value).map(i64::from))
+            }
+            Reference::Frexpf(fun) => {
+                let (mant, value) = fun(f(0));
+                (Return::F32(mant), exp(mant.into(), value).map(i64::from))
+            }
+        }
+    }
+
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Return, i64)> {
+        let f32_fn = matches!(
+            self.reference,
+            Reference::F32(_)
+                | Reference::F32F32(_)
+                | Reference::F32F32F32(_)
+                | Reference::Ldexpf(_)
+                | Reference::Frexpf(_)
+        );
+        let float = |value: f64| {
+            if f32_fn {
+                Param::F32(value as f32)
+            } else {
+                Param::F64(value)
+            }
+        };
+        let exp_addr = Cell::new(0);
+        let mut params = vec![float(self.params[0])];
+        match self.reference {
+            // an int
+            Reference::Ldexp(_) | Reference::Ldexpf(_) => {
+                let exp = self.params[1] as i32;
+                params.push(Param::Usize(u64::from(exp as u32)));
+            }
+            // the function writes the int here
+            Reference::Frexp(_) | Reference::Frexpf(_) => {
+                params.push(Param::HeapFn(Box::new(|helper| {
+                    let addr = helper.malloc(4)?;
+                    exp_addr.set(addr);
+                    Ok(addr)
+                })));
+            }
+            _ => params.extend(self.params[1..].iter().map(|x| float(*x))),
+        }
+        let mut output = if f32_fn {
+            [Return::F32(0.0)]
+        } else {
+            [Return::F64(0.0)]
+        };
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [output] = output;
+        let exp = match exp_addr.get() {
+            0 => 0,
+            addr => vm.helper_mut().read_uint(addr, 4)? as u32 as i32,
+        };
+        Ok((output, exp.into()))
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let (expected, expected_exp) = self.expected();
+        let (output, exp) = self.call(fun_addr, ret_addr, vm)?;
+        let outcome = check_float(self.float_check, expected, output);
+        match expected_exp {
+            Some(expected_exp) if outcome.is_pass() => {
+                Ok(check(expected_exp, exp))
+            }
+            _ => Ok(outcome),
+        }
+    }
+
+    /// compare with a result from [`TESTS_SANITY`], instead of the reference
+    fn test_sanity(
+        &self,
+        result: f64,
+        result_exp: Option<i64>,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let (output, exp) = self.call(fun_addr, ret_addr, vm)?;
+        let output = match output {
+            Return::F32(value) => value.into(),
+            Return::F64(value) => value,
+            _ => unreachable!(),
+        };
+        Ok(check(
+            (result, result_exp),
+            (output, result_exp.map(|_| exp)),
+        ))
+    }
+}
+
+/// the static params for the signature
+fn tests_static(reference: Reference) -> Vec<Vec<f64>> {
+    match reference {
+        Reference::F64F64(_) | Reference::F32F32(_) => TESTS_BINARY
+            .iter()
+            .flat_map(|x| TESTS_BINARY.iter().map(|y| vec![*x, *y]))
+            .collect(),
+        Reference::F64F64F64(_) | Reference::F32F32F32(_) => {
+            TESTS_FMA.iter().map(|params| params.to_vec()).collect()
+        }
+        Reference::Ldexp(_) | Reference::Ldexpf(_) => TESTS_UNARY
+            .iter()
+            .flat_map(|x| {
+                let exps = TESTS_LDEXP.iter().flat_map(|n| [*n, -n]);
+                exps.map(|n| vec![*x, n.into()])
+            })
+            .collect(),
+        _ => TESTS_UNARY.iter().map(|x| vec![*x]).collect(),
+    }
+}
+
+/// random floats of the type, with a lot of special cases
+fn random_params(rng: &mut Rng, reference: Reference) -> Vec<f64> {
+    let (len, f32_fn) = match reference {
+        Reference::F64(_) | Reference::Frexp(_) => (1, false),
+        Reference::F64F64(_) => (2, false),
+        Reference::F64F64F64(_) => (3, false),
+        Reference::F32(_) | Reference::Frexpf(_) => (1, true),
+        Reference::F32F32(_) => (2, true),
+        Reference::F32F32F32(_) => (3, true),
+        Reference::Ldexp(_) | Reference::Ldexpf(_) => {
+            let f32_fn = matches!(reference, Reference::Ldexpf(_));
+            let x = if f32_fn { rng.f32().into() } else { rng.f64() };
+            let n = rng.below(4400) as i64 - 2200;
+            return vec![x, n as f64];
+        }
+    };
+    (0..len)
+        .map(|_| if f32_fn { rng.f32().into() } else { rng.f64() })
+        .collect()
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    for &(fn_sym, reference, float_check) in FUNCTIONS {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        for (i, params) in tests_static(reference).into_iter().enumerate() {
+            let test = TestStatic {
+                fn_sym,
+                reference,
+                float_check,
+                params,
+            };
+            let name = format!("static {} {:?}", i, test.params);
+            report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+        }
+
+        let tests_sanity = TESTS_SANITY.iter().filter(|(x, ..)| *x == fn_sym);
+        for (i, (_, params, result, exp)) in tests_sanity.enumerate() {
+            let test = TestStatic {
+                fn_sym,
+                reference,
+                float_check,
+                params: params.to_vec(),
+            };
+            let name = format!("sanity {} {:?}", i, test.params);
+            report.run(fn_sym, name, vm, |vm| {
+                test.test_sanity(*result, *exp, fun_addr, ret_addr, vm)
+            });
+        }
+
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let test = TestStatic {
+                fn_sym,
+                reference,
+                float_check,
+                params: random_params(&mut rng, reference),
+            };
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(test.fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                report.note(format_args!("input {:?}", test.params));
+            }
+        }
+    }
+}
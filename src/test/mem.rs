@@ -0,0 +1,292 @@
+//! `memcpy`, `memmove`, `memset` and `memcmp`, over a matrix of sizes and
+//! misalignments. The whole guest buffer is compared after the call, so
+//! writing out of the bounds is also detected.
+
+use std::cmp::Ordering;
+
+use crate::random::Rng;
+use crate::report::{check, Outcome, Report};
+use crate::test::common::{check_buffer, read_buffer, GUARD};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+use icicle_mem::perm;
+
+/// the misalignment of the pointers, in relation to a 16 bytes boundary
+const ALIGNS: &[usize] = &[0, 1, 3, 4, 7];
+/// distance from the src to the dst of `memmove`, negative is a backward
+/// overlap
+const SHIFTS: &[isize] = &[-8, -7, -4, -3, -1, 1, 3, 4, 7, 8];
+/// the bytes that differ in `memcmp`, it compares unsigned chars
+const DIFFS: &[(u8, u8)] = &[(0x80, 0x7f), (0x7f, 0x80), (0xff, 0x00)];
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    /// `memcpy(buf + dst, buf + src, len)`
+    Copy { dst: usize, src: usize, len: usize },
+    /// `memmove(buf + dst, buf + src, len)`
+    Move { dst: usize, src: usize, len: usize },
+    /// `memset(buf + dst, value, len)`
+    Set { dst: usize, value: u64, len: usize },
+    /// `memcmp(buf + a, buf + b, len)`
+    Compare { a: usize, b: usize, len: usize },
+}
+
+impl Op {
+    fn fn_sym(&self) -> &'static str {
+        match self {
+            Op::Copy { .. } => "memcpy",
+            Op::Move { .. } => "memmove",
+            Op::Set { .. } => "memset",
+            Op::Compare { .. } => "memcmp",
+        }
+    }
+}
+
+pub struct TestStatic {
+    name: String,
+    /// the content of the guest buffer before the call
+    data: Vec<u8>,
+    op: Op,
+}
+
+impl TestStatic {
+    fn new(name: String, len: usize, op: Op) -> Self {
+        // the same bytes every time, but without a pattern, so a copy from
+        // the wrong offset is detected
+        let mut rng = Rng::new(len as u64);
+        let data = (0..len).map(|_| rng.next_u64() as u8).collect();
+        Self { name, data, op }
+    }
+
+    /// the buffer and return value, after the call. The pointers are returned
+    /// as an offset into the buffer, and the `memcmp` result as the sign
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Vec<u8>, i64)> {
+        // the buffer is allocated before the call, it needs to be kept
+        vm.begin_session();
+        let result = self.run(fun_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Vec<u8>, i64)> {
+        let len = self.data.len() as u64;
+        // the offsets are relative to a 16 bytes boundary
+        let addr = (vm.helper_mut().malloc(len + 15)? + 15) & !15;
+        let mem = &mut vm.helper_mut().icicle.cpu.mem;
+        mem.write_bytes(addr, &self.data, perm::NONE)?;
+
+        let ptr = |offset: usize| Param::Usize(addr + offset as u64);
+        let mut params = match self.op {
+            Op::Copy { dst, src, len } | Op::Move { dst, src, len } => {
+                [ptr(dst), ptr(src), Param::Usize(len as u64)]
+            }
+            Op::Set { dst, value, len } => {
+                [ptr(dst), Param::Usize(value), Param::Usize(len as u64)]
+            }
+            Op::Compare { a, b, len } => {
+                [ptr(a), ptr(b), Param::Usize(len as u64)]
+            }
+        };
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+
+        let data = read_buffer(vm, addr, self.data.len())?;
+        let output = match self.op {
+            // memcmp returns an int
+            Op::Compare { .. } => i64::from((output as u32 as i32).signum()),
+            _ => output.wrapping_sub(addr) as i64,
+        };
+        Ok((data, output))
+    }
+
+    fn expected(&self) -> (Vec<u8>, i64) {
+        let mut data = self.data.clone();
+        match self.op {
+            Op::Copy { dst, src, len } | Op::Move { dst, src, len } => {
+                data.copy_within(src..src + len, dst);
+                (data, dst as i64)
+            }
+            Op::Set { dst, value, len } => {
+                // only the unsigned char is used
+                data[dst..dst + len].fill(value as u8);
+                (data, dst as i64)
+            }
+            Op::Compare { a, b, len } => {
+                let sign = match data[a..a + len].cmp(&data[b..b + len]) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
+                };
+                (data, sign)
+            }
+        }
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let (expected_data, expected_output) = self.expected();
+        let (data, output) = self.call(fun_addr, ret_addr, vm)?;
+        if output != expected_output {
+            return Ok(check(expected_output, output));
+        }
+        Ok(check_buffer(&expected_data, &data))
+    }
+}
+
+/// sizes from 0 to 512: every one up to 64, then a stride of 7, odd so the
+/// tails hit every remainder of the 8 and 16 bytes loops, plus the ones around
+/// the powers of two. Every size with all the misalignments would be too slow
+fn sizes() -> Vec<usize> {
+    let mut sizes: Vec<usize> = (0..=64).chain((71..=512).step_by(7)).collect();
+    for power in [128, 256, 512] {
+        sizes.extend(
+            [power - 1, power, power + 1].iter().filter(|x| **x <= 512),
+        );
+    }
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes
+}
+
+fn align_up(offset: usize) -> usize {
+    (offset + 15) & !15
+}
+
+fn tests_memcpy() -> Vec<TestStatic> {
+    let mut tests = vec![];
+    for len in sizes() {
+        for src_align in ALIGNS {
+            for dst_align in ALIGNS {
+                let src = GUARD + src_align;
+                let dst = align_up(src + len + GUARD) + dst_align;
+                let name =
+                    format!("size {} src+{} dst+{}", len, src_align, dst_align);
+                let op = Op::Copy { dst, src, len };
+                tests.push(TestStatic::new(name, dst + len + GUARD, op));
+            }
+        }
+    }
+    tests
+}
+
+fn tests_memmove() -> Vec<TestStatic> {
+    let mut tests = vec![];
+    for len in sizes() {
+        // also overlap by half of the size, that may already be a shift, a
+        // repeated name would hide the case from the golden and diff checks
+        let half = len as isize / 2;
+        let mut shifts: Vec<_> =
+            SHIFTS.iter().copied().chain([-half, half]).collect();
+        shifts.sort();
+        shifts.dedup();
+        for shift in shifts.into_iter().filter(|shift| *shift != 0) {
+            for align in ALIGNS {
+                let src = GUARD + align_up(shift.unsigned_abs()) + align;
+                let dst = src.checked_add_signed(shift).unwrap();
+                let name =
+                    format!("size {} src+{} shift {}", len, align, shift);
+                let op = Op::Move { dst, src, len };
+                let buf_len = src.max(dst) + len + GUARD;
+                tests.push(TestStatic::new(name, buf_len, op));
+            }
+        }
+    }
+    tests
+}
+
+fn tests_memset() -> Vec<TestStatic> {
+    let mut tests = vec![];
+    for len in sizes() {
+        for align in ALIGNS {
+            let dst = GUARD + align;
+            // the upper bits of the int are ignored
+            let value = 0x1a5;
+            let name =
+                format!("size {} dst+{} value 0x{:x}", len, align, value);
+            let op = Op::Set { dst, value, len };
+            tests.push(TestStatic::new(name, dst + len + GUARD, op));
+        }
+    }
+    for value in 0..=0xff {
+        for len in [1, 7, 33] {
+            let dst = GUARD + 1;
+            let name = format!("size {} dst+1 value 0x{:x}", len, value);
+            let op = Op::Set { dst, value, len };
+            tests.push(TestStatic::new(name, dst + len + GUARD, op));
+        }
+    }
+    tests
+}
+
+fn tests_memcmp() -> Vec<TestStatic> {
+    let mut tests = vec![];
+    for len in sizes() {
+        for (a_align, b_align) in ALIGNS.iter().zip(ALIGNS.iter().rev()) {
+            let a = GUARD + a_align;
+            let b = align_up(a + len + GUARD) + b_align;
+            let buf_len = b + len + GUARD;
+            let name = format!("size {} a+{} b+{}", len, a_align, b_align);
+            let mut test =
+                TestStatic::new(name, buf_len, Op::Compare { a, b, len });
+            // equal areas, but the bytes after them differ, so reading past
+            // the end changes the result
+            test.data.copy_within(a..a + len, b);
+            test.data[a + len] = 0x00;
+            test.data[b + len] = 0xff;
+            // the first and last bytes differ
+            let mut positions = vec![0, len.saturating_sub(1)];
+            positions.dedup();
+            for pos in positions.into_iter().filter(|_| len != 0) {
+                for (x, y) in DIFFS {
+                    let mut data = test.data.clone();
+                    data[a + pos] = *x;
+                    data[b + pos] = *y;
+                    let name = format!(
+                        "{} diff at {} 0x{:02x} 0x{:02x}",
+                        test.name, pos, x, y
+                    );
+                    let op = test.op;
+                    tests.push(TestStatic { name, data, op });
+                }
+            }
+            tests.push(test);
+        }
+    }
+    tests
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    let tests = [
+        tests_memcpy(),
+        tests_memmove(),
+        tests_memset(),
+        tests_memcmp(),
+    ];
+    for test in tests.into_iter().flatten() {
+        let fn_sym = test.op.fn_sym();
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        report.run(fn_sym, &test.name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+}
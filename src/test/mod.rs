@@ -1,7 +1,31 @@
 pub mod atoll;
+pub mod common;
 pub mod cos;
+pub mod errno;
+pub mod fake_kernel;
+pub mod fenv;
+pub mod fpclassify;
+pub mod getenv;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod host;
+pub mod libm;
+pub mod mem;
+pub mod printf;
+pub mod rand;
 pub mod rint;
 pub mod rintf;
+pub mod setjmp;
+pub mod snprintf;
 pub mod sin;
 pub mod strcat;
+pub mod strcmp;
+pub mod strcopy;
 pub mod strlen;
+pub mod strsearch;
+pub mod strtok;
+pub mod strtod;
+pub mod strtol;
+pub mod sysconf;
@@ -0,0 +1,251 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Input, Param, Return, Vm};
+use anyhow::Result;
+
+/// format the value like the C `%.{precision}g`
+pub fn format_g(value: f64, precision: usize) -> String {
+    if value.is_nan() {
+        return if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .into();
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.into();
+    }
+    let precision = precision.max(1);
+    // the exponent after rounding to the precision
+    let sci = format!("{:.*e}", precision - 1, value);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let trim = |number: &str| {
+        if number.contains('.') {
+            number
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        } else {
+            number.to_string()
+        }
+    };
+    if exp < -4 || exp >= precision as i32 {
+        let sign = if exp < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim(mantissa), sign, exp.abs())
+    } else {
+        let decimals = (precision as i32 - 1 - exp) as usize;
+        trim(&format!("{:.*}", decimals, value))
+    }
+}
+
+pub struct PrintfTest {
+    format: &'static [u8],
+    params: Vec<Input>,
+    result: String,
+}
+
+impl PrintfTest {
+    fn test_on_vm(
+        &self,
+        printf_addr: u64,
+        fflush_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // stdout is only written on fflush
+        vm.begin_session();
+        let result = self.run(printf_addr, fflush_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        printf_addr: u64,
+        fflush_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let params = self.params.iter().map(Input::param);
+        let mut params: Vec<_> = [Param::HeapData(self.format)]
+            .into_iter()
+            .chain(params)
+            .collect();
+        let mut output = [Return::Usize(0)];
+        vm.call(printf_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // fflush(NULL) flush all the streams
+        let mut params = [Param::Usize(0)];
+        vm.call(fflush_addr, ret_addr, &mut params, &mut [])?;
+        // printf returns the number of bytes written, as an int
+        let stdout = String::from_utf8_lossy(&vm.stdout()).into_owned();
+        let expected = (self.result.len() as u64, self.result.clone());
+        Ok(check(expected, (output & 0xffff_ffff, stdout)))
+    }
+}
+
+pub struct PutsTest {
+    data: &'static [u8],
+}
+
+impl PutsTest {
+    fn test_on_vm(
+        &self,
+        puts_addr: u64,
+        fflush_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        vm.begin_session();
+        let result = self.run(puts_addr, fflush_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        puts_addr: u64,
+        fflush_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut params = [Param::HeapData(self.data)];
+        vm.call(puts_addr, ret_addr, &mut params, &mut [])?;
+        let mut params = [Param::Usize(0)];
+        vm.call(fflush_addr, ret_addr, &mut params, &mut [])?;
+        // puts adds a new line, and don't write the \x00
+        let len = self.data.iter().position(|x| *x == 0).unwrap();
+        let result: Vec<u8> =
+            self.data[..len].iter().copied().chain([b'\n']).collect();
+        Ok(check((result, vec![]), (vm.stdout(), vm.stderr())))
+    }
+}
+
+pub struct FputsStderrTest {
+    data: &'static [u8],
+}
+
+impl FputsStderrTest {
+    fn test_on_vm(
+        &self,
+        fputs_addr: u64,
+        stderr_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // stderr is unbuffered, no fflush is required
+        let stderr = vm.helper_mut().read_ptr(stderr_addr)?;
+        let mut params = [Param::HeapData(self.data), Param::Usize(stderr)];
+        vm.call(fputs_addr, ret_addr, &mut params, &mut [])?;
+        let len = self.data.iter().position(|x| *x == 0).unwrap();
+        let expected = (self.data[..len].to_vec(), vec![]);
+        Ok(check(expected, (vm.stderr(), vm.stdout())))
+    }
+}
+
+pub const TESTS_PRINTF: &[f64] = &[
+    0.0,
+    -0.0,
+    1.0,
+    -2.5,
+    0.1,
+    1.0 / 3.0,
+    123456789.0,
+    1.0e16,
+    1.0e17,
+    1.0e-4,
+    1.0e-5,
+    1.0e300,
+    -1.0e-300,
+    f64::MAX,
+    f64::MIN_POSITIVE,
+    5.0e-324,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+/// `%u %.17g %u %.17g`, the ints and the floats are passed in separate
+/// registers on x86_64 and aarch64
+pub const TESTS_PRINTF_MIXED: &[(u32, f64, u32, f64)] = &[
+    (1, 2.5, 3, 0.1),
+    (0, -0.0, 42, 1.0e300),
+    (0xffff_ffff, f64::MIN_POSITIVE, 7, 1.0 / 3.0),
+];
+pub const TESTS_PUTS: [&[u8]; 4] = [
+    b"\x00",
+    b"hello\x00",
+    b"two\nlines\x00",
+    b"\xff\xfe\x01\x00ignored",
+];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "printf";
+    let Some(printf_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(puts_addr) = report.lookup(vm, "puts") else {
+        return;
+    };
+    let Some(fputs_addr) = report.lookup(vm, "fputs") else {
+        return;
+    };
+    let Some(fflush_addr) = report.lookup(vm, "fflush") else {
+        return;
+    };
+    let Some(stderr_addr) = report.lookup(vm, "stderr") else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    for (i, value) in TESTS_PRINTF.iter().enumerate() {
+        let test = PrintfTest {
+            format: b"%.17g\x00",
+            params: vec![Input::F64(*value)],
+            result: format_g(*value, 17),
+        };
+        let name = format!("static {} f64({})", i, value);
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(printf_addr, fflush_addr, ret_addr, vm)
+        });
+    }
+
+    for (i, (a, b, c, d)) in TESTS_PRINTF_MIXED.iter().enumerate() {
+        let test = PrintfTest {
+            format: b"%u %.17g %u %.17g\x00",
+            params: vec![
+                Input::Usize((*a).into()),
+                Input::F64(*b),
+                Input::Usize((*c).into()),
+                Input::F64(*d),
+            ],
+            result: format!(
+                "{} {} {} {}",
+                a,
+                format_g(*b, 17),
+                c,
+                format_g(*d, 17)
+            ),
+        };
+        let name = format!("mixed {} ({}, {}, {}, {})", i, a, b, c, d);
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(printf_addr, fflush_addr, ret_addr, vm)
+        });
+    }
+
+    let tests_puts = TESTS_PUTS.into_iter().map(|data| PutsTest { data });
+    for (i, test) in tests_puts.enumerate() {
+        report.run("puts", format!("static {}", i), vm, |vm| {
+            test.test_on_vm(puts_addr, fflush_addr, ret_addr, vm)
+        });
+    }
+
+    let tests_fputs =
+        TESTS_PUTS.into_iter().map(|data| FputsStderrTest { data });
+    for (i, test) in tests_fputs.enumerate() {
+        report.run("fputs", format!("static {}", i), vm, |vm| {
+            test.test_on_vm(fputs_addr, stderr_addr, ret_addr, vm)
+        });
+    }
+}
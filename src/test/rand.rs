@@ -0,0 +1,128 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// the musl `rand` implementation, a 64 bits LCG
+struct MuslRand {
+    seed: u64,
+}
+
+impl MuslRand {
+    fn new(seed: u32) -> Self {
+        Self {
+            seed: u64::from(seed).wrapping_sub(1),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.seed = self.seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        self.seed >> 33
+    }
+}
+
+pub struct TestSession {
+    seed: u32,
+    calls: usize,
+}
+
+impl TestSession {
+    fn test_on_vm(
+        &self,
+        srand_addr: u64,
+        rand_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // srand and rand need to share the seed
+        vm.begin_session();
+        let result = self.run(srand_addr, rand_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        srand_addr: u64,
+        rand_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut reference = MuslRand::new(self.seed);
+        let mut params = [Param::Usize(self.seed.into())];
+        vm.call(srand_addr, ret_addr, &mut params, &mut [])?;
+        let mut outputs = vec![];
+        for _ in 0..self.calls {
+            let mut output = [Return::Usize(0)];
+            vm.call(rand_addr, ret_addr, &mut [], &mut output)?;
+            let [Return::Usize(output)] = output else { unreachable!() };
+            // rand returns an int
+            outputs.push(output & 0xffff_ffff);
+        }
+        let expected: Vec<_> =
+            (0..self.calls).map(|_| reference.next()).collect();
+        Ok(check(expected, outputs))
+    }
+}
+
+/// `srand` and `rand` in separate calls, out of a session, the snapshot is
+/// restored before the `rand`, so the seed set by `srand` must be gone
+pub struct TestIsolated {
+    seed: u32,
+}
+
+impl TestIsolated {
+    fn test_on_vm(
+        &self,
+        srand_addr: u64,
+        rand_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut params = [Param::Usize(self.seed.into())];
+        vm.call(srand_addr, ret_addr, &mut params, &mut [])?;
+        let mut output = [Return::Usize(0)];
+        vm.call(rand_addr, ret_addr, &mut [], &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // the seed after the load is 0, the same as `srand(1)`
+        let expected = MuslRand::new(1).next();
+        Ok(check(expected, output & 0xffff_ffff))
+    }
+}
+
+pub const TESTS_SESSION: &[(u32, usize)] =
+    &[(0, 8), (1, 8), (2, 8), (1337, 16), (0xffff_ffff, 16)];
+/// any seed but 1, that is the same as the seed after the load
+pub const TESTS_ISOLATED: &[u32] = &[0, 2, 1337, 0xffff_ffff];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "rand";
+    let Some(srand_addr) = report.lookup(vm, "srand") else {
+        return;
+    };
+    let Some(rand_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    let tests_session = TESTS_SESSION.iter().map(|(seed, calls)| TestSession {
+        seed: *seed,
+        calls: *calls,
+    });
+    for (i, test) in tests_session.enumerate() {
+        let name = format!("session {} seed({})", i, test.seed);
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(srand_addr, rand_addr, ret_addr, vm)
+        });
+    }
+
+    let tests_isolated = TESTS_ISOLATED
+        .iter()
+        .map(|seed| TestIsolated { seed: *seed });
+    for (i, test) in tests_isolated.enumerate() {
+        let name = format!("isolated {} seed({})", i, test.seed);
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(srand_addr, rand_addr, ret_addr, vm)
+        });
+    }
+}
@@ -1,23 +1,43 @@
+use crate::random::{self, Rng};
+use crate::report::{check, check_float, FloatCheck, Outcome, Report};
+use crate::shrink::{self, Failure};
+use crate::test::fenv::{self, Rounding, ROUNDINGS};
 use crate::vm::{Param, Return, Vm};
 use anyhow::Result;
 
+/// the result is exact, also the sign of the zeros, but the reference
+/// quiets the signaling NaNs of the random inputs like the host does
+const FLOAT_CHECK: FloatCheck = FloatCheck::AnyNan;
+
 pub struct TestStatic {
     param: f64,
     result: f64,
 }
 
 impl TestStatic {
-    fn test_on_vm(
+    fn call(
         &self,
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<f64> {
         let mut params = [Param::F64(self.param)];
         let mut output = [Return::F64(0.0)];
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::F64(output)] = output else { unreachable!() };
-        Ok(output == self.result)
+        Ok(output)
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let output = self.call(fun_addr, ret_addr, vm)?;
+        let (expected, output) =
+            (Return::F64(self.result), Return::F64(output));
+        Ok(check_float(FLOAT_CHECK, expected, output))
     }
 }
 
@@ -33,20 +53,213 @@ pub const TESTS_STATIC: &[f64] = &[
     1.0e-6,
     1.0e+6,
 ];
-pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
+
+/// the ties round to even, and the values next to them. They fit in 32 bits,
+/// for `lrint`
+pub const TESTS_HALFWAY: &[f64] = &[
+    0.5,
+    1.5,
+    2.5,
+    3.5,
+    4.5,
+    -0.5,
+    -1.5,
+    -2.5,
+    0.49999999999999994,
+    0.5000000000000001,
+    -0.49999999999999994,
+    2147483646.5,
+    -2147483647.5,
+];
+
+/// the sign of the zeros, the biggest values with a fraction, and the ones
+/// that are not changed
+const TESTS_SPECIAL: &[f64] = &[
+    -0.0,
+    4503599627370495.5,
+    -4503599627370495.5,
+    4503599627370496.0,
+    1.0e300,
+    f64::MIN_POSITIVE,
+    -5.0e-324,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+
+/// `rint`, `nearbyint` or `lrint` after `fesetround`, in the same session
+pub struct TestRounding {
+    fn_sym: &'static str,
+    rounding: Rounding,
+    param: f64,
+}
+
+impl TestRounding {
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Return> {
+        vm.begin_session();
+        let result = self.run(fun_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Return> {
+        fenv::set_rounding(vm, ret_addr, self.rounding)?;
+        let mut params = [Param::F64(self.param)];
+        // `lrint` returns a long
+        let mut output = match self.fn_sym {
+            "lrint" => [Return::Usize(0)],
+            _ => [Return::F64(0.0)],
+        };
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [output] = output;
+        Ok(output)
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let result = self.rounding.round(self.param);
+        match self.call(fun_addr, ret_addr, vm)? {
+            Return::Usize(output) => {
+                // long have the size of a pointer
+                let mask = u64::MAX >> (64 - vm.helper().ptr_size() * 8);
+                Ok(check(result as i64 as u64 & mask, output & mask))
+            }
+            output => Ok(check_float(FLOAT_CHECK, Return::F64(result), output)),
+        }
+    }
+}
+
+/// find the simplest value that still fails
+pub fn minimal_failure(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    param: f64,
+) -> Option<Failure<f64>> {
+    let expected = |param: &f64| Return::F64(param.round_ties_even());
+    shrink::shrink_with(param, FLOAT_CHECK, expected, |param| {
+        let test = TestStatic {
+            param: *param,
+            result: 0.0,
+        };
+        Ok(Return::F64(test.call(fun_addr, ret_addr, vm)?))
+    })
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
     const FN_SYM: &str = "rint";
-    let fun_addr = vm.lookup_symbol(FN_SYM);
-    let ret_addr = vm.lookup_symbol("_dlstart");
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
 
-    let tests_static = TESTS_STATIC.into_iter().map(|value| TestStatic {
+    let tests_static = TESTS_STATIC.iter().chain(TESTS_HALFWAY);
+    let tests_static = tests_static.map(|value| TestStatic {
         param: *value,
-        result: value.round(),
+        // the default rounding mode is to nearest, ties to even
+        result: value.round_ties_even(),
     });
     for (i, test) in tests_static.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test static {} f64({})", FN_SYM, i, test.param);
-            return Ok(false);
+        let name = format!("static {} f64({})", i, test.param);
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.param)
+            {
+                report.note(failure);
+            }
+        }
+    }
+
+    // test random values, with a lot of halfway and special cases
+    let mut rng = Rng::for_fn(FN_SYM);
+    for i in 0..random::cases() {
+        let param = rng.f64();
+        let test = TestStatic {
+            param,
+            // the default rounding mode is to nearest, ties to even
+            result: param.round_ties_even(),
+        };
+        let name = format!(
+            "random {} seed 0x{:016x} f64({:?})",
+            i,
+            random::seed(),
+            test.param
+        );
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.param)
+            {
+                report.note(failure);
+            }
+        }
+    }
+
+    // every rounding mode, `lrint` is only called with the values that fit
+    for fn_sym in ["rint", "nearbyint", "lrint"] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        let params: Vec<f64> = match fn_sym {
+            "lrint" => TESTS_HALFWAY.to_vec(),
+            _ => [TESTS_HALFWAY, TESTS_SPECIAL].concat(),
+        };
+        for rounding in ROUNDINGS {
+            for (i, param) in params.iter().enumerate() {
+                let test = TestRounding {
+                    fn_sym,
+                    rounding,
+                    param: *param,
+                };
+                let name =
+                    format!("static {} {:?} f64({:?})", i, rounding, param);
+                report.run(fn_sym, name, vm, |vm| {
+                    test.test_on_vm(fun_addr, ret_addr, vm)
+                });
+            }
+        }
+
+        if fn_sym == "lrint" {
+            continue;
+        }
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let test = TestRounding {
+                fn_sym,
+                rounding: *rng.choose(&ROUNDINGS),
+                param: rng.f64(),
+            };
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                report.note(format_args!(
+                    "input {:?} f64({:?})",
+                    test.rounding, test.param
+                ));
+            }
         }
     }
-    Ok(true)
 }
@@ -1,23 +1,42 @@
+use crate::random::{self, Rng};
+use crate::report::{check_float, FloatCheck, Outcome, Report};
+use crate::shrink::{self, Failure};
 use crate::vm::{Param, Return, Vm};
 use anyhow::Result;
 
+/// the result is exact, also the sign of the zeros, but the reference
+/// quiets the signaling NaNs of the random inputs like the host does
+const FLOAT_CHECK: FloatCheck = FloatCheck::AnyNan;
+
 pub struct TestStatic {
     param: f32,
     result: f32,
 }
 
 impl TestStatic {
-    fn test_on_vm(
+    fn call(
         &self,
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<f32> {
         let mut params = [Param::F32(self.param)];
         let mut output = [Return::F32(0.0)];
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::F32(output)] = output else { unreachable!() };
-        Ok(output == self.result)
+        Ok(output)
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let output = self.call(fun_addr, ret_addr, vm)?;
+        let (expected, output) =
+            (Return::F32(self.result), Return::F32(output));
+        Ok(check_float(FLOAT_CHECK, expected, output))
     }
 }
 
@@ -32,21 +51,85 @@ pub const TESTS_STATIC: &[f32] = &[
     90.00001,
     1.0e-6,
     1.0e+6,
+    // the ties round to even
+    0.5,
+    1.5,
+    2.5,
+    -0.5,
+    -2.5,
+    0.49999997,
+    8388607.5,
 ];
-pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
+
+/// find the simplest value that still fails
+pub fn minimal_failure(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    param: f32,
+) -> Option<Failure<f32>> {
+    let expected = |param: &f32| Return::F32(param.round_ties_even());
+    shrink::shrink_with(param, FLOAT_CHECK, expected, |param| {
+        let test = TestStatic {
+            param: *param,
+            result: 0.0,
+        };
+        Ok(Return::F32(test.call(fun_addr, ret_addr, vm)?))
+    })
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
     const FN_SYM: &str = "rintf";
-    let fun_addr = vm.lookup_symbol(FN_SYM);
-    let ret_addr = vm.lookup_symbol("_dlstart");
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
 
     let tests_static = TESTS_STATIC.into_iter().map(|value| TestStatic {
         param: *value,
-        result: value.round(),
+        // the default rounding mode is to nearest, ties to even
+        result: value.round_ties_even(),
     });
     for (i, test) in tests_static.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test static {} f32({})", FN_SYM, i, test.param);
-            return Ok(false);
+        let name = format!("static {} f32({})", i, test.param);
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.param)
+            {
+                report.note(failure);
+            }
+        }
+    }
+
+    // test random values, with a lot of halfway and special cases
+    let mut rng = Rng::for_fn(FN_SYM);
+    for i in 0..random::cases() {
+        let param = rng.f32();
+        let test = TestStatic {
+            param,
+            // the default rounding mode is to nearest, ties to even
+            result: param.round_ties_even(),
+        };
+        let name = format!(
+            "random {} seed 0x{:016x} f32({:?})",
+            i,
+            random::seed(),
+            test.param
+        );
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.param)
+            {
+                report.note(failure);
+            }
         }
     }
-    Ok(true)
 }
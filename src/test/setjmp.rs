@@ -0,0 +1,86 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// big enough for the jmp_buf of any arch
+const JMP_BUF_LEN: u64 = 0x200;
+
+pub struct TestSession {
+    value: u64,
+    result: u64,
+}
+
+impl TestSession {
+    fn test_on_vm(
+        &self,
+        setjmp_addr: u64,
+        longjmp_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // longjmp need the jmp_buf and stack left by setjmp
+        vm.begin_session();
+        let result = self.run(setjmp_addr, longjmp_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        setjmp_addr: u64,
+        longjmp_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let jmp_buf = vm.helper_mut().malloc(JMP_BUF_LEN)?;
+
+        // setjmp returns 0 the first time
+        let mut params = [Param::Usize(jmp_buf)];
+        let mut output = [Return::Usize(u64::MAX)];
+        vm.call(setjmp_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        if output & 0xffff_ffff != 0 {
+            return Ok(check(0, output & 0xffff_ffff));
+        }
+
+        // longjmp returns from setjmp again, to the same return addr
+        let mut params = [Param::Usize(jmp_buf), Param::Usize(self.value)];
+        let mut output = [Return::Usize(u64::MAX)];
+        vm.call(longjmp_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        Ok(check(self.result, output & 0xffff_ffff))
+    }
+}
+
+pub const TESTS_SESSION: &[(u64, u64)] = &[
+    (1, 1),
+    (2, 2),
+    (0x7fff_ffff, 0x7fff_ffff),
+    (0xffff_ffff, 0xffff_ffff),
+    // longjmp with 0 makes setjmp return 1
+    (0, 1),
+];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "setjmp";
+    let Some(setjmp_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(longjmp_addr) = report.lookup(vm, "longjmp") else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    let tests_session =
+        TESTS_SESSION.iter().map(|(value, result)| TestSession {
+            value: *value,
+            result: *result,
+        });
+    for (i, test) in tests_session.enumerate() {
+        let name = format!("session {} value({})", i, test.value);
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(setjmp_addr, longjmp_addr, ret_addr, vm)
+        });
+    }
+}
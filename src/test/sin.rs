@@ -1,41 +1,86 @@
 use super::cos::TESTS_STATIC;
+use crate::report::{check_float, FloatCheck, Outcome, Report};
+use crate::shrink::{self, Failure};
 use crate::vm::{Param, Return, Vm};
 use anyhow::Result;
 
+/// musl and the host libm are not correctly rounded, they may differ in the
+/// last bit
+const FLOAT_CHECK: FloatCheck = FloatCheck::Ulps(1);
+
 pub struct SinTestStatic {
     param: f64,
     result: f64,
 }
 
 impl SinTestStatic {
-    fn test_on_vm(
+    fn call(
         &self,
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<f64> {
         let mut params = [Param::F64(self.param)];
         let mut output = [Return::F64(0.0)];
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::F64(output)] = output else { unreachable!() };
-        Ok(output == self.result)
+        Ok(output)
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let output = self.call(fun_addr, ret_addr, vm)?;
+        let (expected, output) =
+            (Return::F64(self.result), Return::F64(output));
+        Ok(check_float(FLOAT_CHECK, expected, output))
     }
 }
 
-pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
+/// find the simplest value that still fails
+pub fn minimal_failure(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    param: f64,
+) -> Option<Failure<f64>> {
+    let expected = |param: &f64| Return::F64(param.sin());
+    shrink::shrink_with(param, FLOAT_CHECK, expected, |param| {
+        let test = SinTestStatic {
+            param: *param,
+            result: 0.0,
+        };
+        Ok(Return::F64(test.call(fun_addr, ret_addr, vm)?))
+    })
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
     const FN_SYM: &str = "sin";
-    let fun_addr = vm.lookup_symbol(FN_SYM);
-    let ret_addr = vm.lookup_symbol("_dlstart");
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
 
     let tests_static = TESTS_STATIC.into_iter().map(|value| SinTestStatic {
         param: *value,
         result: value.sin(),
     });
     for (i, test) in tests_static.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test static {} f64({})", FN_SYM, i, test.param);
-            return Ok(false);
+        let name = format!("static {} f64({})", i, test.param);
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.param)
+            {
+                report.note(failure);
+            }
         }
     }
-    Ok(true)
 }
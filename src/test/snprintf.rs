@@ -0,0 +1,330 @@
+//! `snprintf` of doubles with `%a`, `%e`, `%f` and `%g`. musl format them
+//! with long double arithmetic and big integers, so this covers the x87 and
+//! the soft float emulation. The Rust formatter is the reference, it also
+//! prints the exact digits and round the ties to even.
+
+use std::cell::Cell;
+
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::test::common::{check_buffer, read_buffer, GUARD, SENTINEL};
+use crate::test::printf;
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+pub const FORMATS: &[&str] = &[
+    "%a", "%.0a", "%.1a", "%.3a", "%.12a", "%.13a", "%.20a", "%A", "%e",
+    "%.0e", "%.3e", "%.16e", "%.30e", "%E", "%f", "%.0f", "%.3f", "%.20f",
+    "%F", "%g", "%.0g", "%.3g", "%.17g", "%G", "%#g", "%#.0f", "%#.0e",
+    "%#.0a", "%+e", "% f", "%-14.3e", "%014.3f", "%+015.2a", "%20g",
+];
+
+/// the values of the `printf` tests, and the ones that are hard to round
+pub const TESTS_STATIC: &[f64] = &[
+    0.5,
+    1.5,
+    2.5,
+    0.125,
+    0.375,
+    9.5,
+    99.5,
+    999999.5,
+    0.000123456789,
+    123456.789,
+    1.0e21,
+    1.0e22,
+    9223372036854775808.0,
+    std::f64::consts::PI,
+    std::f64::consts::E,
+    1.0e-310,
+    -f64::MIN_POSITIVE,
+    // 0x1.fffffffffffffp+0, round up to 0x2 with `%.0a`
+    1.9999999999999998,
+    // 0x1.08p+0 and 0x1.18p+0 are ties for `%.1a`
+    1.03125,
+    1.09375,
+];
+
+/// a conversion like `%+08.3e`
+struct Spec {
+    plus: bool,
+    space: bool,
+    alt: bool,
+    left: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    conv: u8,
+}
+
+impl Spec {
+    fn parse(format: &str) -> Self {
+        let format = format.strip_prefix('%').unwrap().as_bytes();
+        let flags = format.iter().take_while(|c| b"+ #-0".contains(c));
+        let flags: Vec<u8> = flags.copied().collect();
+        let rest = std::str::from_utf8(&format[flags.len()..]).unwrap();
+        let (rest, conv) = rest.split_at(rest.len() - 1);
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => {
+                (width, Some(precision.parse().unwrap()))
+            }
+            None => (rest, None),
+        };
+        Self {
+            plus: flags.contains(&b'+'),
+            space: flags.contains(&b' '),
+            alt: flags.contains(&b'#'),
+            left: flags.contains(&b'-'),
+            zero: flags.contains(&b'0'),
+            width: width.parse().unwrap_or(0),
+            precision,
+            conv: conv.as_bytes()[0],
+        }
+    }
+}
+
+/// the `%e` digits, the exponent has a sign and at least two digits
+fn exp_form(value: f64, precision: usize, alt: bool) -> String {
+    let sci = format!("{:.*e}", precision, value);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let point = if alt && precision == 0 { "." } else { "" };
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}{}e{}{:02}", mantissa, point, sign, exp.abs())
+}
+
+fn fixed_form(value: f64, precision: usize, alt: bool) -> String {
+    let point = if alt && precision == 0 { "." } else { "" };
+    format!("{:.*}{}", precision, value, point)
+}
+
+/// `%g` is `%e` or `%f`, depending on the exponent after rounding, and the
+/// zeros after the point are removed without `#`
+fn general_form(value: f64, precision: usize, alt: bool) -> String {
+    if !alt {
+        return printf::format_g(value, precision);
+    }
+    let precision = precision.max(1);
+    let sci = format!("{:.*e}", precision - 1, value);
+    let (_, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if exp < -4 || exp >= precision as i32 {
+        exp_form(value, precision - 1, true)
+    } else {
+        fixed_form(value, (precision as i32 - 1 - exp) as usize, true)
+    }
+}
+
+/// `%a`, musl normalize the subnormals so the first digit is always 1,
+/// or 2 when it's rounded up
+fn hex_form(value: f64, precision: Option<usize>, alt: bool) -> String {
+    let bits = value.to_bits();
+    let (mut mant, mut exp) = (bits & ((1 << 52) - 1), (bits >> 52) as i32);
+    if exp != 0 {
+        mant |= 1 << 52;
+        exp -= 1023;
+    } else if mant != 0 {
+        let shift = mant.leading_zeros() - 11;
+        mant <<= shift;
+        exp = -1022 - shift as i32;
+    }
+    let (lead, frac) = match precision {
+        // rounded to nearest, ties to even
+        Some(precision) if precision < 13 => {
+            let shift = 4 * (13 - precision);
+            let (q, rest) = (mant >> shift, mant & ((1 << shift) - 1));
+            let half = 1 << (shift - 1);
+            let q = q + u64::from(rest > half || (rest == half && q & 1 == 1));
+            let frac = q & ((1 << (4 * precision)) - 1);
+            let frac = if precision == 0 {
+                String::new()
+            } else {
+                format!("{:0width$x}", frac, width = precision)
+            };
+            (q >> (4 * precision), frac)
+        }
+        Some(precision) => {
+            let frac = format!("{:013x}", mant & ((1 << 52) - 1));
+            (mant >> 52, format!("{:0<width$}", frac, width = precision))
+        }
+        None => {
+            let frac = format!("{:013x}", mant & ((1 << 52) - 1));
+            (mant >> 52, frac.trim_end_matches('0').to_string())
+        }
+    };
+    let point = if !frac.is_empty() || alt { "." } else { "" };
+    format!("0x{}{}{}p{:+}", lead, point, frac, exp)
+}
+
+/// the output of the conversion, with the sign and the padding
+pub fn format_fp(format: &str, value: f64) -> String {
+    let spec = Spec::parse(format);
+    let abs = value.abs();
+    let precision = spec.precision.unwrap_or(6);
+    let body = if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        "inf".to_string()
+    } else {
+        match spec.conv.to_ascii_lowercase() {
+            b'a' => hex_form(abs, spec.precision, spec.alt),
+            b'e' => exp_form(abs, precision, spec.alt),
+            b'f' => fixed_form(abs, precision, spec.alt),
+            b'g' => general_form(abs, precision, spec.alt),
+            _ => unreachable!(),
+        }
+    };
+    // also the NaNs have a sign
+    let sign = if value.is_sign_negative() {
+        "-"
+    } else if spec.plus {
+        "+"
+    } else if spec.space {
+        " "
+    } else {
+        ""
+    };
+    // the zeros are after the `0x`, and not used for inf and nan
+    let (prefix, body) = match body.strip_prefix("0x") {
+        Some(body) => ("0x", body),
+        None => ("", body.as_str()),
+    };
+    let len = sign.len() + prefix.len() + body.len();
+    let pad = spec.width.saturating_sub(len);
+    let output = if spec.left {
+        format!("{}{}{}{}", sign, prefix, body, " ".repeat(pad))
+    } else if spec.zero && value.is_finite() {
+        format!("{}{}{}{}", sign, prefix, "0".repeat(pad), body)
+    } else {
+        format!("{}{}{}{}", " ".repeat(pad), sign, prefix, body)
+    };
+    if spec.conv.is_ascii_uppercase() {
+        output.to_ascii_uppercase()
+    } else {
+        output
+    }
+}
+
+pub struct TestStatic {
+    format: &'static str,
+    value: f64,
+    /// the size param, None is the len of the output with the NUL
+    size: Option<u64>,
+}
+
+impl TestStatic {
+    fn size(&self, output: &str) -> usize {
+        self.size.map_or(output.len() + 1, |size| size as usize)
+    }
+
+    /// the buffer after the call, and the returned len
+    fn expected(&self) -> (Vec<u8>, u64) {
+        let output = format_fp(self.format, self.value);
+        let size = self.size(&output);
+        let mut buffer = vec![SENTINEL; size + GUARD];
+        // the output is cut, but the full len is returned
+        if size != 0 {
+            let len = output.len().min(size - 1);
+            buffer[..len].copy_from_slice(&output.as_bytes()[..len]);
+            buffer[len] = 0;
+        }
+        (buffer, output.len() as u64)
+    }
+
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Vec<u8>, u64)> {
+        let size = self.size(&format_fp(self.format, self.value));
+        let buffer = vec![SENTINEL; size + GUARD];
+        let format = format!("{}\x00", self.format);
+        let buffer_addr = Cell::new(0);
+        // the value is the first float param, it goes into xmm0/d0 after the
+        // 3 int params, not into the 4th register
+        let mut params = [
+            Param::heap_data_at(&buffer, &buffer_addr),
+            Param::Usize(size as u64),
+            Param::HeapData(format.as_bytes()),
+            Param::F64(self.value),
+        ];
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+
+        let data = read_buffer(vm, buffer_addr.get(), buffer.len())?;
+        // the len is an int
+        Ok((data, output & 0xffff_ffff))
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let (expected_data, expected_output) = self.expected();
+        let (data, output) = self.call(fun_addr, ret_addr, vm)?;
+        if output != expected_output {
+            return Ok(check(expected_output, output));
+        }
+        Ok(check_buffer(&expected_data, &data))
+    }
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "snprintf";
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    let values = printf::TESTS_PRINTF.iter().chain(TESTS_STATIC);
+    for (i, value) in values.enumerate() {
+        for format in FORMATS {
+            let test = TestStatic {
+                format,
+                value: *value,
+                size: None,
+            };
+            let name = format!("static {} {} f64({:?})", i, format, value);
+            report.run(FN_SYM, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+        }
+    }
+
+    // the output is cut to the size
+    for size in [0, 1, 2, 5, 17] {
+        let test = TestStatic {
+            format: "%.10e",
+            value: -std::f64::consts::PI,
+            size: Some(size),
+        };
+        report.run(FN_SYM, format!("static size {}", size), vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+
+    let mut rng = Rng::for_fn(FN_SYM);
+    for i in 0..random::cases() {
+        let test = TestStatic {
+            format: rng.choose(FORMATS),
+            value: rng.f64(),
+            size: None,
+        };
+        let name = format!("random {} seed 0x{:016x}", i, random::seed());
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            report.note(format_args!(
+                "input {} f64({:?})",
+                test.format, test.value
+            ));
+        }
+    }
+}
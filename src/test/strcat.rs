@@ -1,23 +1,26 @@
 use crate::{
-    test::strlen,
+    random::{self, Rng},
+    report::{check, Outcome, Report},
+    shrink::{self, Failure},
+    test::{common::c_str, strlen},
     vm::{IcicleHelper, Param, Return, Vm},
 };
 use anyhow::Result;
 use icicle_mem::perm;
 
-pub struct StrcatTestStatic {
-    src: &'static [u8],
-    dst: &'static [u8],
-    result: &'static [u8],
+pub struct StrcatTestStatic<'a> {
+    src: &'a [u8],
+    dst: &'a [u8],
+    result: &'a [u8],
 }
 
-impl StrcatTestStatic {
-    fn test_on_vm(
+impl StrcatTestStatic<'_> {
+    fn call(
         &self,
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<Vec<u8>> {
         // we need to make sure the correct space is allocated for the result
         let dst_with_space: Vec<u8> = self
             .dst
@@ -36,8 +39,47 @@ impl StrcatTestStatic {
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::CString(output)] = output else { unreachable!() };
         //TODO check that output points to the same as param dst
-        Ok(output == self.result)
+        Ok(output)
     }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let output = self.call(fun_addr, ret_addr, vm)?;
+        Ok(check(self.result, output.as_slice()))
+    }
+}
+
+/// find the smallest (dst, src) strings that still fail
+pub fn minimal_failure(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    dst: &[u8],
+    src: &[u8],
+) -> Option<Failure<(Vec<u8>, Vec<u8>)>> {
+    let expected = |(dst, src): &(Vec<u8>, Vec<u8>)| {
+        Return::CString([c_str(dst), c_str(src)].concat())
+    };
+    let input = (dst.to_vec(), src.to_vec());
+    shrink::shrink(input, expected, |(dst, src)| {
+        let result = [c_str(dst), c_str(src)].concat();
+        // the strings always end, even if all the NULs are removed
+        let terminated = |data: &Vec<u8>| {
+            let mut data = data.clone();
+            data.push(0);
+            data
+        };
+        let test = StrcatTestStatic {
+            src: &terminated(src),
+            dst: &terminated(dst),
+            result: &result,
+        };
+        Ok(Return::CString(test.call(fun_addr, ret_addr, vm)?))
+    })
 }
 
 pub struct StrcatTestLong {
@@ -52,7 +94,7 @@ impl StrcatTestLong {
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<Outcome> {
         let write_str = |data: u8, len: u64, extra: u64| {
             move |vm: &mut IcicleHelper| {
                 // TODO improve that
@@ -81,8 +123,16 @@ impl StrcatTestLong {
         let result = (0..self.res.3)
             .map(|_| self.res.2)
             .chain((0..self.res.1).map(|_| self.res.0));
-        let all_equal = output.into_iter().zip(result).all(|(x, y)| x == y);
-        Ok(all_equal)
+        // the strings are too long, only the first different byte is reported
+        let mismatch = output
+            .into_iter()
+            .zip(result)
+            .enumerate()
+            .find(|(_, (x, y))| x != y);
+        Ok(match mismatch {
+            None => Outcome::Pass,
+            Some((i, (x, y))) => check((i, y), (i, x)),
+        })
     }
 }
 
@@ -99,10 +149,14 @@ const TESTS_LONG: [((u8, u64), (u8, u64), (u8, u64, u8, u64)); 2] = [
     ((0xff, 0x1000), (0xfe, 0x1000), (0xff, 0x1000, 0xfe, 0x1000)),
 ];
 
-pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
     const FN_SYM: &str = "strcat";
-    let fun_addr = vm.lookup_symbol(FN_SYM);
-    let ret_addr = vm.lookup_symbol("_dlstart");
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
 
     // test strlen tests with an empty string
     let tests_strlen =
@@ -119,9 +173,16 @@ pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
         .into_iter()
         .map(|(src, dst, result)| StrcatTestStatic { src, dst, result });
     for (i, test) in tests_strlen.chain(tests_strcat).enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test static {}", FN_SYM, i);
-            return Ok(false);
+        let name = format!("static {}", i);
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.dst, test.src)
+            {
+                report.note(failure);
+            }
         }
     }
 
@@ -130,10 +191,33 @@ pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
         .into_iter()
         .map(|(src, dst, res)| StrcatTestLong { src, dst, res });
     for (i, test) in tests_long.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test long {}", FN_SYM, i);
-            return Ok(false);
+        report.run(FN_SYM, format!("long {}", i), vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+
+    // test random strings, with NULs in the middle
+    let mut rng = Rng::for_fn(FN_SYM);
+    for i in 0..random::cases() {
+        let src = rng.c_string(0x40);
+        let dst = rng.c_string(0x40);
+        let result = [c_str(&dst), c_str(&src)].concat();
+        let test = StrcatTestStatic {
+            src: &src,
+            dst: &dst,
+            result: &result,
+        };
+        let name = format!("random {} seed 0x{:016x}", i, random::seed());
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            report.note(format_args!("input {:02x?} {:02x?}", dst, src));
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, &dst, &src)
+            {
+                report.note(failure);
+            }
         }
     }
-    Ok(true)
 }
@@ -0,0 +1,178 @@
+//! `strcmp` and `strncmp`, only the sign of the result is checked.
+
+use std::cmp::Ordering;
+
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::test::common::c_str;
+use crate::test::strlen;
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// pairs that differ in each position of the first words, and in the high bit
+const TESTS_STATIC: &[(&[u8], &[u8])] = &[
+    (b"\x00", b"\x00"),
+    (b"a\x00", b"\x00"),
+    (b"abcdefghijklmnop\x00", b"abcdefghijklmnop\x00"),
+    (b"abcdefghijklmnop\x00", b"abcdefghijklmnoq\x00"),
+    (b"abcdefghijklmnop\x00", b"abcdefgh\x00"),
+    (b"abcdefg\x80\x00", b"abcdefg\x7f\x00"),
+    (b"abcdefghijk\xff\x00", b"abcdefghijk\x01\x00"),
+    (b"\xff\x00", b"\x7f\x00"),
+    (b"test\x00abc\x00", b"test\x00abd\x00"),
+];
+
+pub struct TestStatic {
+    fn_sym: &'static str,
+    a: Vec<u8>,
+    b: Vec<u8>,
+    /// the max number of chars compared, only for `strncmp`
+    n: Option<u64>,
+}
+
+impl TestStatic {
+    /// the sign of the result, the chars are compared as unsigned
+    fn result(&self) -> i32 {
+        let (mut a, mut b) = (c_str(&self.a), c_str(&self.b));
+        if let Some(n) = self.n {
+            a = &a[..a.len().min(n as usize)];
+            b = &b[..b.len().min(n as usize)];
+        }
+        match a.cmp(b) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        }
+    }
+
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<i32> {
+        let mut params =
+            vec![Param::HeapData(&self.a), Param::HeapData(&self.b)];
+        params.extend(self.n.map(Param::Usize));
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // the result is an int
+        Ok((output as u32 as i32).signum())
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        Ok(check(self.result(), self.call(fun_addr, ret_addr, vm)?))
+    }
+}
+
+/// the pairs from this module, and every pair of the `strlen` strings
+fn pairs() -> Vec<(Vec<u8>, Vec<u8>)> {
+    let strlen_strings: Vec<_> =
+        strlen::TESTS_STATIC.iter().map(|(data, _)| *data).collect();
+    let strlen_pairs = strlen_strings
+        .iter()
+        .flat_map(|a| strlen_strings.iter().map(move |b| (*a, *b)));
+    TESTS_STATIC
+        .iter()
+        .copied()
+        .chain(strlen_pairs)
+        .map(|(a, b)| (a.to_vec(), b.to_vec()))
+        .collect()
+}
+
+/// a string and a copy of it, that may be changed in one place or cut
+fn random_pair(rng: &mut Rng) -> (Vec<u8>, Vec<u8>) {
+    let a = rng.c_string(40);
+    let mut b = a.clone();
+    let len = c_str(&a).len();
+    match rng.below(4) {
+        0 => {}
+        1 => {
+            let pos = rng.below(len as u64 + 1) as usize;
+            b.truncate(pos);
+            b.push(0);
+        }
+        _ => {
+            let pos = rng.below(len as u64 + 1) as usize;
+            b[pos] = rng.byte();
+            // the NUL may have been replaced
+            b.push(0);
+        }
+    }
+    (a, b)
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    if let Some(fun_addr) = report.lookup(vm, "strcmp") {
+        for (i, (a, b)) in pairs().into_iter().enumerate() {
+            let test = TestStatic {
+                fn_sym: "strcmp",
+                a,
+                b,
+                n: None,
+            };
+            report.run(test.fn_sym, format!("static {}", i), vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+        }
+    }
+
+    if let Some(fun_addr) = report.lookup(vm, "strncmp") {
+        for (i, (a, b)) in pairs().into_iter().enumerate() {
+            // before, at and after the first difference, and a big one, even
+            // for 32 bits
+            let diff = c_str(&a)
+                .iter()
+                .zip(c_str(&b))
+                .position(|(x, y)| x != y)
+                .unwrap_or(c_str(&a).len().min(c_str(&b).len()))
+                as u64;
+            let ns = [0, diff, diff + 1, u64::from(u32::MAX)];
+            for n in ns.into_iter().chain(diff.checked_sub(1)) {
+                let test = TestStatic {
+                    fn_sym: "strncmp",
+                    a: a.clone(),
+                    b: b.clone(),
+                    n: Some(n),
+                };
+                let name = format!("static {} n {}", i, n);
+                report.run(test.fn_sym, name, vm, |vm| {
+                    test.test_on_vm(fun_addr, ret_addr, vm)
+                });
+            }
+        }
+    }
+
+    // random strings, with NULs in the middle
+    for fn_sym in ["strcmp", "strncmp"] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let (a, b) = random_pair(&mut rng);
+            let n = (fn_sym == "strncmp").then(|| rng.below(48));
+            let test = TestStatic { fn_sym, a, b, n };
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                report.note(format_args!(
+                    "input {:02x?} {:02x?} {:?}",
+                    test.a, test.b, test.n
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! The bounded copy functions: `stpcpy`, `strncpy`, `stpncpy`, `strlcpy`,
+//! `strlcat` and `strncat`. The dst buffer is filled with a sentinel after the
+//! string, and read back after the call, so the padding and the bytes that
+//! must stay untouched are checked.
+
+use std::cell::Cell;
+
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::test::common::{c_str, check_buffer, read_buffer, GUARD, SENTINEL};
+use crate::test::strlen;
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// the dst strings of `strlcat` and `strncat`
+const DSTS: &[&[u8]] = &[b"\x00", b"abc\x00", b"0123456789abcdef\x00"];
+
+/// copy the string to the buffer at `start`, with the NUL
+fn copy(buffer: &mut [u8], start: usize, data: &[u8]) {
+    buffer[start..start + data.len()].copy_from_slice(data);
+    buffer[start + data.len()] = 0;
+}
+
+pub struct TestStatic {
+    fn_sym: &'static str,
+    /// the start of the dst buffer, the rest is filled with the sentinel
+    dst: Vec<u8>,
+    src: Vec<u8>,
+    /// the size param, `stpcpy` don't have one
+    n: Option<u64>,
+}
+
+impl TestStatic {
+    fn buffer(&self) -> Vec<u8> {
+        let n = self.n.unwrap_or(0) as usize;
+        let len = self.dst.len() + self.src.len() + n + GUARD;
+        let mut buffer = self.dst.clone();
+        buffer.resize(len, SENTINEL);
+        buffer
+    }
+
+    /// the dst buffer after the call, and the output. Pointers are returned
+    /// as an offset into the dst buffer
+    fn expected(&self) -> (Vec<u8>, u64) {
+        let mut buffer = self.buffer();
+        let src = c_str(&self.src);
+        let n = self.n.unwrap_or(0) as usize;
+        let output = match self.fn_sym {
+            "stpcpy" => {
+                copy(&mut buffer, 0, src);
+                src.len()
+            }
+            "strncpy" | "stpncpy" => {
+                let len = src.len().min(n);
+                buffer[..len].copy_from_slice(&src[..len]);
+                // padded with NULs up to n, not terminated if the src is
+                // longer
+                buffer[len..n].fill(0);
+                if self.fn_sym == "strncpy" {
+                    0
+                } else {
+                    len
+                }
+            }
+            "strlcpy" => {
+                if n != 0 {
+                    copy(&mut buffer, 0, &src[..src.len().min(n - 1)]);
+                }
+                src.len()
+            }
+            "strlcat" => {
+                // the dst may not be terminated inside the size
+                let start = buffer[..n].iter().position(|x| *x == 0);
+                match start {
+                    Some(start) => {
+                        let len = src.len().min(n - start - 1);
+                        copy(&mut buffer, start, &src[..len]);
+                        start + src.len()
+                    }
+                    None => n + src.len(),
+                }
+            }
+            "strncat" => {
+                let start = c_str(&self.dst).len();
+                copy(&mut buffer, start, &src[..src.len().min(n)]);
+                0
+            }
+            _ => unreachable!(),
+        };
+        (buffer, output as u64)
+    }
+
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<(Vec<u8>, u64)> {
+        let buffer = self.buffer();
+        let dst_addr = Cell::new(0);
+        let mut params = vec![
+            Param::heap_data_at(&buffer, &dst_addr),
+            Param::HeapData(&self.src),
+        ];
+        params.extend(self.n.map(Param::Usize));
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+
+        let dst_addr = dst_addr.get();
+        let data = read_buffer(vm, dst_addr, buffer.len())?;
+        // the strl* functions return a len
+        let output = if self.fn_sym.starts_with("strl") {
+            output
+        } else {
+            output.wrapping_sub(dst_addr)
+        };
+        Ok((data, output))
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let (expected_data, expected_output) = self.expected();
+        let (data, output) = self.call(fun_addr, ret_addr, vm)?;
+        if output != expected_output {
+            return Ok(check(expected_output, output));
+        }
+        Ok(check_buffer(&expected_data, &data))
+    }
+}
+
+/// the strings from the `strlen` tests, and a longer one
+fn srcs() -> Vec<Vec<u8>> {
+    let strlen_strings = strlen::TESTS_STATIC.iter().map(|(data, _)| *data);
+    let long: &[u8] = b"0123456789abcdefghijklmnopqrstuv\x00";
+    strlen_strings.chain([long]).map(<[u8]>::to_vec).collect()
+}
+
+/// the sizes around the len of the strings
+fn sizes(dst: &[u8], src: &[u8]) -> Vec<u64> {
+    let (dst_len, src_len) = (c_str(dst).len(), c_str(src).len());
+    let mut sizes = vec![0, 1];
+    for len in [src_len, dst_len, dst_len + src_len] {
+        sizes.extend([len.saturating_sub(1), len, len + 1, len + 8]);
+    }
+    sizes.sort();
+    sizes.dedup();
+    sizes.into_iter().map(|n| n as u64).collect()
+}
+
+fn tests() -> Vec<(String, TestStatic)> {
+    let mut tests = vec![];
+    for (i, src) in srcs().into_iter().enumerate() {
+        let test = TestStatic {
+            fn_sym: "stpcpy",
+            dst: vec![],
+            src: src.clone(),
+            n: None,
+        };
+        tests.push((format!("static {}", i), test));
+        for fn_sym in ["strncpy", "stpncpy", "strlcpy"] {
+            for n in sizes(&[], &src) {
+                let test = TestStatic {
+                    fn_sym,
+                    dst: vec![],
+                    src: src.clone(),
+                    n: Some(n),
+                };
+                tests.push((format!("static {} n {}", i, n), test));
+            }
+        }
+        for fn_sym in ["strlcat", "strncat"] {
+            for (j, dst) in DSTS.iter().enumerate() {
+                for n in sizes(dst, &src) {
+                    let test = TestStatic {
+                        fn_sym,
+                        dst: dst.to_vec(),
+                        src: src.clone(),
+                        n: Some(n),
+                    };
+                    let name = format!("static {} dst {} n {}", i, j, n);
+                    tests.push((name, test));
+                }
+            }
+        }
+    }
+    tests
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    for (name, test) in tests() {
+        let Some(fun_addr) = report.lookup(vm, test.fn_sym) else {
+            continue;
+        };
+        report.run(test.fn_sym, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+
+    // random strings, with NULs in the middle
+    for fn_sym in ["strncpy", "stpncpy", "strlcpy", "strlcat", "strncat"] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let dst = if fn_sym.ends_with("cat") {
+                rng.c_string(20)
+            } else {
+                vec![]
+            };
+            let src = rng.c_string(40);
+            let n = rng.below(64);
+            let test = TestStatic {
+                fn_sym,
+                dst,
+                src,
+                n: Some(n),
+            };
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                report.note(format_args!(
+                    "input {:02x?} {:02x?} {}",
+                    test.dst, test.src, n
+                ));
+            }
+        }
+    }
+}
@@ -1,27 +1,62 @@
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::shrink::{self, Failure};
 use crate::vm::{IcicleHelper, Param, Return, Vm};
 use anyhow::Result;
 use icicle_mem::perm;
 
-pub struct StrlenTestStatic {
-    data: &'static [u8],
+pub struct StrlenTestStatic<'a> {
+    data: &'a [u8],
     result: u64,
 }
 
-impl StrlenTestStatic {
-    fn test_on_vm(
+impl StrlenTestStatic<'_> {
+    fn call(
         &self,
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<u64> {
         let mut params = [Param::HeapData(&self.data)];
         let mut output = [Return::Usize(0)];
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::Usize(output)] = output else { unreachable!() };
-        Ok(output == self.result)
+        Ok(output)
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        Ok(check(self.result, self.call(fun_addr, ret_addr, vm)?))
     }
 }
 
+/// find the smallest string that still fails
+pub fn minimal_failure(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    data: &[u8],
+) -> Option<Failure<Vec<u8>>> {
+    let expected = |data: &Vec<u8>| {
+        let len = data.iter().position(|x| *x == 0).unwrap_or(data.len());
+        Return::Usize(len as u64)
+    };
+    shrink::shrink(data.to_vec(), expected, |data| {
+        // the string always ends, even if all the NULs are removed
+        let mut data = data.clone();
+        data.push(0);
+        let test = StrlenTestStatic {
+            data: &data,
+            result: 0,
+        };
+        Ok(Return::Usize(test.call(fun_addr, ret_addr, vm)?))
+    })
+}
+
 pub struct StrlenTestLong {
     data: u8,
     data_len: u64,
@@ -33,7 +68,7 @@ impl StrlenTestLong {
         fun_addr: u64,
         ret_addr: u64,
         vm: &mut impl Vm,
-    ) -> Result<bool> {
+    ) -> Result<Outcome> {
         let write_str = |vm: &mut IcicleHelper| {
             // TODO improve that
             let addr = vm.malloc(self.data_len + 1)?;
@@ -53,7 +88,7 @@ impl StrlenTestLong {
         let mut output = [Return::Usize(0)];
         vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
         let [Return::Usize(output)] = output else { unreachable!() };
-        Ok(output == self.data_len)
+        Ok(check(self.data_len, output))
     }
 }
 
@@ -72,19 +107,30 @@ pub const TESTS_STATIC: [(&[u8], u64); 10] = [
     (FUNNY_STRING.as_bytes(), FUNNY_STRING.len() as u64 - 1), //-1 for \x00
 ];
 pub const TESTS_LONG: [(u8, u64); 2] = [(0x01, 0x1234), (0xff, 0x4321)];
-pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
     const FN_SYM: &str = "strlen";
-    let fun_addr = vm.lookup_symbol(FN_SYM);
-    let ret_addr = vm.lookup_symbol("_dlstart");
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
 
     // test short strings
     let tests_static = TESTS_STATIC
         .into_iter()
         .map(|(data, result)| StrlenTestStatic { data, result });
     for (i, test) in tests_static.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test static {} ", FN_SYM, i);
-            return Ok(false);
+        let name = format!("static {}", i);
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, test.data)
+            {
+                report.note(failure);
+            }
         }
     }
 
@@ -93,10 +139,31 @@ pub fn all_tests(vm: &mut impl Vm) -> Result<bool> {
         .into_iter()
         .map(|(data, data_len)| StrlenTestLong { data, data_len });
     for (i, test) in tests_long.enumerate() {
-        if !test.test_on_vm(fun_addr, ret_addr, vm)? {
-            println!("{} Error test long {} ", FN_SYM, i);
-            return Ok(false);
+        report.run(FN_SYM, format!("long {}", i), vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+
+    // test random strings, with NULs in the middle
+    let mut rng = Rng::for_fn(FN_SYM);
+    for i in 0..random::cases() {
+        let data = rng.c_string(0x40);
+        let result = data.iter().position(|x| *x == 0).unwrap() as u64;
+        let test = StrlenTestStatic {
+            data: &data,
+            result,
+        };
+        let name = format!("random {} seed 0x{:016x}", i, random::seed());
+        let passed = report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+        if !passed {
+            report.note(format_args!("input {:02x?}", data));
+            if let Some(failure) =
+                minimal_failure(fun_addr, ret_addr, vm, &data)
+            {
+                report.note(failure);
+            }
         }
     }
-    Ok(true)
 }
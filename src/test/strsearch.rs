@@ -0,0 +1,305 @@
+//! `strchr`, `strrchr`, `strstr`, `strpbrk`, `strspn` and `strcspn`. musl
+//! search a word at a time, so the strings cross the word boundaries in
+//! different places.
+
+use std::cell::Cell;
+
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::test::common::c_str;
+use crate::test::strlen;
+use crate::vm::{ptr_offset, Param, Return, Vm};
+use anyhow::Result;
+
+/// strings that cross a few words, with repeated patterns for `strstr`
+const STRINGS: &[&[u8]] = &[
+    b"abcdefghijklmnopqrstuvwxyz0123456789\x00",
+    b"aaaaaaaaaaaaaaaaaaaaaaab\x00",
+    b"abababababababababac\x00",
+    b"\x80\x81\xfe\xff\x80\x81\xfe\xff\x80\x00",
+];
+
+/// the sets of `strpbrk`, `strspn` and `strcspn`, without the NUL
+const SETS: &[&[u8]] = &[
+    b"",
+    b"t",
+    b"ets",
+    b"\xff\xfe",
+    b"\x01\x02\x03\x04",
+    b"abcdefghijklmnopqrstuvwxyz",
+    "ç😂¢".as_bytes(),
+];
+
+/// the second param of the function
+#[derive(Clone, Debug)]
+enum Arg {
+    /// an int, converted to char by the function
+    Char(u64),
+    /// a string, the NUL is added when calling
+    Str(Vec<u8>),
+}
+
+/// call the function, returns the output and the addr of the data
+fn call(
+    fun_addr: u64,
+    ret_addr: u64,
+    vm: &mut impl Vm,
+    data: &[u8],
+    arg: &Arg,
+) -> Result<(u64, u64)> {
+    let data_addr = Cell::new(0);
+    let arg_str;
+    let arg = match arg {
+        Arg::Char(value) => Param::Usize(*value),
+        Arg::Str(value) => {
+            arg_str = [value.as_slice(), b"\x00"].concat();
+            Param::HeapData(&arg_str)
+        }
+    };
+    let mut params = [Param::heap_data_at(data, &data_addr), arg];
+    let mut output = [Return::Usize(0)];
+    vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+    let [Return::Usize(output)] = output else { unreachable!() };
+    Ok((output, data_addr.get()))
+}
+
+/// a function that returns a pointer into the data, or NULL
+pub struct TestFind {
+    fn_sym: &'static str,
+    data: Vec<u8>,
+    arg: Arg,
+    /// the offset of the result, None is NULL
+    result: Option<u64>,
+}
+
+impl TestFind {
+    fn new(fn_sym: &'static str, data: Vec<u8>, arg: Arg) -> Self {
+        let s = c_str(&data);
+        let result = match (fn_sym, &arg) {
+            // the NUL can also be found, only the unsigned char is used
+            ("strchr", Arg::Char(c)) => data[..=s.len()]
+                .iter()
+                .position(|x| *x == *c as u8)
+                .map(|pos| pos as u64),
+            ("strrchr", Arg::Char(c)) => data[..=s.len()]
+                .iter()
+                .rposition(|x| *x == *c as u8)
+                .map(|pos| pos as u64),
+            ("strstr", Arg::Str(needle)) if needle.is_empty() => Some(0),
+            ("strstr", Arg::Str(needle)) => s
+                .windows(needle.len())
+                .position(|window| window == needle)
+                .map(|pos| pos as u64),
+            ("strpbrk", Arg::Str(set)) => {
+                s.iter().position(|x| set.contains(x)).map(|pos| pos as u64)
+            }
+            _ => unreachable!(),
+        };
+        Self {
+            fn_sym,
+            data,
+            arg,
+            result,
+        }
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let (output, data_addr) =
+            call(fun_addr, ret_addr, vm, &self.data, &self.arg)?;
+        Ok(check(self.result, ptr_offset(output, data_addr)))
+    }
+}
+
+/// a function that returns the len of the prefix, with or without the set
+pub struct TestSpan {
+    fn_sym: &'static str,
+    data: Vec<u8>,
+    set: Vec<u8>,
+    result: u64,
+}
+
+impl TestSpan {
+    fn new(fn_sym: &'static str, data: Vec<u8>, set: Vec<u8>) -> Self {
+        let s = c_str(&data);
+        let accept = fn_sym == "strspn";
+        let result = s
+            .iter()
+            .position(|x| set.contains(x) != accept)
+            .unwrap_or(s.len());
+        Self {
+            fn_sym,
+            data,
+            set,
+            result: result as u64,
+        }
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let arg = Arg::Str(self.set.clone());
+        let (output, _) = call(fun_addr, ret_addr, vm, &self.data, &arg)?;
+        Ok(check(self.result, output))
+    }
+}
+
+/// the strings from the `strlen` tests, and the ones from this module
+fn strings() -> Vec<Vec<u8>> {
+    let strlen_strings = strlen::TESTS_STATIC.iter().map(|(data, _)| *data);
+    strlen_strings
+        .chain(STRINGS.iter().copied())
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+/// every char in the string, and a few that are not, or have extra bits
+fn chars(data: &[u8]) -> Vec<u64> {
+    let mut chars: Vec<u64> =
+        c_str(data).iter().map(|c| u64::from(*c)).collect();
+    chars.extend([0, u64::from(b'x'), 0x80, 0xff, 0x100 | u64::from(b't')]);
+    chars.sort();
+    chars.dedup();
+    chars
+}
+
+/// parts of the string, at the start, middle and end, and some that are not
+/// in it
+fn needles(data: &[u8]) -> Vec<Vec<u8>> {
+    let s = c_str(data);
+    let mut needles = vec![vec![], b"tesx".to_vec(), b"\xff\xfe".to_vec()];
+    needles.push([s, b"x"].concat());
+    for len in [1, 2, 3, 4, 5, 8, 16]
+        .into_iter()
+        .filter(|len| *len <= s.len())
+    {
+        for start in [0, (s.len() - len) / 2, s.len() - len] {
+            needles.push(s[start..start + len].to_vec());
+        }
+    }
+    needles.sort();
+    needles.dedup();
+    needles
+}
+
+/// every byte but `a`, it fills the whole bitmap used by musl
+fn sets() -> Vec<Vec<u8>> {
+    let all_but_a = (1..=0xff).filter(|c| *c != b'a').collect();
+    SETS.iter()
+        .map(|set| set.to_vec())
+        .chain([all_but_a])
+        .collect()
+}
+
+/// a random string, with the arg made from it most of the time, so it's found
+fn random_test(rng: &mut Rng, fn_sym: &'static str) -> TestFind {
+    let data = rng.c_string(40);
+    let s = c_str(&data);
+    let arg = match fn_sym {
+        "strchr" | "strrchr" if !s.is_empty() && rng.below(4) != 0 => {
+            Arg::Char(u64::from(*rng.choose(s)))
+        }
+        "strchr" | "strrchr" => Arg::Char(u64::from(rng.byte())),
+        "strstr" if rng.below(4) != 0 => {
+            let start = rng.below(s.len() as u64 + 1) as usize;
+            let len = rng.below((s.len() - start) as u64 + 1) as usize;
+            Arg::Str(s[start..start + len].to_vec())
+        }
+        _ => Arg::Str(c_str(&rng.c_string(8)).to_vec()),
+    };
+    TestFind::new(fn_sym, data, arg)
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    for fn_sym in ["strchr", "strrchr"] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        for (i, data) in strings().into_iter().enumerate() {
+            for c in chars(&data) {
+                let test = TestFind::new(fn_sym, data.clone(), Arg::Char(c));
+                let name = format!("static {} char 0x{:x}", i, c);
+                report.run(fn_sym, name, vm, |vm| {
+                    test.test_on_vm(fun_addr, ret_addr, vm)
+                });
+            }
+        }
+    }
+
+    if let Some(fun_addr) = report.lookup(vm, "strstr") {
+        for (i, data) in strings().into_iter().enumerate() {
+            for needle in needles(&data) {
+                let name = format!("static {} needle {:02x?}", i, needle);
+                let test =
+                    TestFind::new("strstr", data.clone(), Arg::Str(needle));
+                report.run("strstr", name, vm, |vm| {
+                    test.test_on_vm(fun_addr, ret_addr, vm)
+                });
+            }
+        }
+    }
+
+    if let Some(fun_addr) = report.lookup(vm, "strpbrk") {
+        for (i, data) in strings().into_iter().enumerate() {
+            for (j, set) in sets().into_iter().enumerate() {
+                let test =
+                    TestFind::new("strpbrk", data.clone(), Arg::Str(set));
+                report.run(
+                    "strpbrk",
+                    format!("static {} set {}", i, j),
+                    vm,
+                    |vm| test.test_on_vm(fun_addr, ret_addr, vm),
+                );
+            }
+        }
+    }
+
+    for fn_sym in ["strspn", "strcspn"] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        for (i, data) in strings().into_iter().enumerate() {
+            for (j, set) in sets().into_iter().enumerate() {
+                let test = TestSpan::new(fn_sym, data.clone(), set);
+                report.run(
+                    fn_sym,
+                    format!("static {} set {}", i, j),
+                    vm,
+                    |vm| test.test_on_vm(fun_addr, ret_addr, vm),
+                );
+            }
+        }
+    }
+
+    // random strings, with NULs in the middle
+    for fn_sym in ["strchr", "strrchr", "strstr", "strpbrk"] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let test = random_test(&mut rng, fn_sym);
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                report.note(format_args!(
+                    "input {:02x?} {:02x?}",
+                    test.data, test.arg
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! `strtod` and `strtof`. musl convert the decimal strings with big integers,
+//! so this is a lot of multiplications and divisions. The results are
+//! compared bitwise with the Rust parser, that is also correctly rounded.
+
+use std::cell::Cell;
+
+use crate::random::{self, Rng};
+use crate::report::{check, check_same, Outcome, Report};
+use crate::test::common::{c_str, EINVAL, ERANGE};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+pub const TESTS_STATIC: &[&str] = &[
+    "",
+    " ",
+    "0",
+    "-0",
+    "-0.0e10",
+    " \t\n\x0b\x0c\r1.5",
+    "+.5",
+    "-.",
+    ".",
+    "1.",
+    "1.e2",
+    "1..2",
+    "1e",
+    "1e+",
+    "1e-5x",
+    "1.5E+3",
+    "0.1",
+    "0.3",
+    "1e23",
+    "8.589973e9",
+    "9007199254740993",
+    "123456789012345678901234567890123456789012345678901234567890",
+    "00000000000000000000000000000000000001",
+    "2.2250738585072011e-308",
+    "2.2250738585072012e-308",
+    "4.9406564584124654e-324",
+    "2.4703282292062327e-324",
+    "2.4703282292062328e-324",
+    "1.7976931348623157e308",
+    "1.7976931348623158e308",
+    "1.7976931348623159e308",
+    "7.038531e-26",
+    "1.00000005960464477539062499",
+    "3.4028235e38",
+    "3.4028236e38",
+    "3.40282357e38",
+    "1.17549435e-38",
+    "1.4e-45",
+    "7.0e-46",
+    "1e-400",
+    "1e400",
+    "0e99999999999",
+    "1e-99999999999999999999",
+    "1e99999999999999999999",
+    "0x1p0",
+    "0x1.8p1",
+    "-0x1.4p3",
+    "0X1.FFFFFFFFFFFFFp1023",
+    "0x1.fffffffffffff8p1023",
+    "0x1p-1074",
+    "0x1p-1075",
+    "0x1.8p-1075",
+    "0x.8p-1073",
+    "0x123456789abcdef0123p0",
+    "0x1.000001p0",
+    "0x1.000003p0",
+    "0x1.fffffep127",
+    "0x1.ffffffp127",
+    "0x1p",
+    "0x1p+",
+    "0x1pz",
+    "0x",
+    "0x.",
+    "0x.p1",
+    "0xg",
+    "0x0p99999",
+    "0x1p99999999999999999999",
+    "0x1p-99999999999999999999",
+    "inf",
+    "INF",
+    "-Infinity",
+    "infinit",
+    "infinityx",
+    "in",
+    "nan",
+    "-NaN",
+    "nan()",
+    "nan(123abc_)",
+    "nan(12",
+    "nan(!)",
+    "na",
+];
+
+/// the halfway points between two floats, as `(2m + 1) * 2^exp`
+const HALFWAY: &[(u64, i32)] = &[
+    // next to 1
+    ((1 << 53) + 1, -53),
+    ((1 << 53) + 3, -53),
+    ((1 << 24) + 1, -24),
+    ((1 << 24) + 3, -24),
+    // half of the smallest subnormals
+    (1, -1075),
+    (3, -1075),
+    (1, -150),
+    (3, -150),
+    // below the smallest normals
+    ((1 << 53) - 1, -1075),
+    ((1 << 24) - 1, -150),
+    // after the biggest normals, they round to infinity
+    ((1 << 54) - 1, 970),
+    ((1 << 25) - 1, 103),
+];
+
+#[derive(Clone, Copy, Debug)]
+enum Float {
+    F32,
+    F64,
+}
+
+impl Float {
+    /// the bits of the mantissa, with the implicit one
+    fn precision(self) -> u32 {
+        match self {
+            Float::F32 => 24,
+            Float::F64 => 53,
+        }
+    }
+
+    fn exp_bits(self) -> u32 {
+        match self {
+            Float::F32 => 8,
+            Float::F64 => 11,
+        }
+    }
+
+    fn inf_bits(self) -> u64 {
+        ((1 << self.exp_bits()) - 1) << (self.precision() - 1)
+    }
+
+    fn sign_bit(self) -> u64 {
+        1 << (self.exp_bits() + self.precision() - 1)
+    }
+
+    fn to_return(self, bits: u64) -> Return {
+        match self {
+            Float::F32 => Return::F32(f32::from_bits(bits as u32)),
+            Float::F64 => Return::F64(f64::from_bits(bits)),
+        }
+    }
+
+    /// the bits of a decimal number, without the sign
+    fn parse(self, text: &str) -> u64 {
+        match self {
+            Float::F32 => u64::from(text.parse::<f32>().unwrap().to_bits()),
+            Float::F64 => text.parse::<f64>().unwrap().to_bits(),
+        }
+    }
+
+    /// the bits of `(mant + sticky) * 2^exp`, rounded to nearest, ties to
+    /// even. sticky is a non zero fraction below the last bit of mant
+    fn round(self, mant: u128, sticky: bool, exp: i64) -> u64 {
+        let precision = i64::from(self.precision());
+        let bias = (1 << (self.exp_bits() - 1)) - 1;
+        let len = 128 - i64::from(mant.leading_zeros());
+        // the subnormals have less bits
+        let keep = precision.min(len + exp - 1 + bias + precision - 1);
+        let shift = len - keep;
+        let (mut q, mut exp) = if shift <= 0 {
+            (mant << -shift, exp + shift)
+        } else if shift > 128 {
+            // below half of the smallest subnormal
+            (0, exp + shift)
+        } else {
+            let q = mant.checked_shr(shift as u32).unwrap_or(0);
+            let rest = mant & (u128::MAX >> (128 - shift));
+            let half = 1 << (shift - 1);
+            let up = rest > half || (rest == half && (sticky || q & 1 == 1));
+            (q + u128::from(up), exp + shift)
+        };
+        if q == 1 << precision {
+            q >>= 1;
+            exp += 1;
+        }
+        if q >> (precision - 1) == 0 {
+            // subnormal, or zero
+            return q as u64;
+        }
+        let biased = exp + precision - 1 + bias;
+        if biased >= (1 << self.exp_bits()) - 1 {
+            return self.inf_bits();
+        }
+        ((biased as u64) << (precision - 1))
+            | (q as u64 - (1 << (precision - 1)))
+    }
+
+    /// the errno for a finite result, None if musl may set ERANGE or not,
+    /// for the subnormals and the smallest normal
+    fn errno(self, bits: u64, nonzero: bool) -> Option<i32> {
+        if bits == self.inf_bits() || (bits == 0 && nonzero) {
+            Some(ERANGE)
+        } else if bits != 0 && bits <= 1 << (self.precision() - 1) {
+            None
+        } else {
+            Some(0)
+        }
+    }
+}
+
+/// what the function returns, with the endptr as an offset into the string
+#[derive(Debug)]
+struct Parsed {
+    value: Return,
+    end: u64,
+    /// None if it's not checked
+    errno: Option<i32>,
+}
+
+/// the exponent after the `e` or `p` at pos, and the end of it. The big
+/// ones are saturated
+fn exponent(data: &[u8], pos: usize) -> Option<(i64, usize)> {
+    let mut pos = pos + 1;
+    let neg = data.get(pos) == Some(&b'-');
+    if matches!(data.get(pos), Some(b'-' | b'+')) {
+        pos += 1;
+    }
+    let digits = data[pos..].iter().take_while(|c| c.is_ascii_digit());
+    let len = digits.clone().count();
+    if len == 0 {
+        return None;
+    }
+    let exp = digits.fold(0i64, |exp, c| {
+        (exp * 10 + i64::from(c - b'0')).min(1_000_000_000)
+    });
+    Some((if neg { -exp } else { exp }, pos + len))
+}
+
+/// the musl implementation
+fn strto(data: &[u8], float: Float) -> Parsed {
+    let invalid = Parsed {
+        value: float.to_return(0),
+        end: 0,
+        errno: Some(EINVAL),
+    };
+    let mut pos = data
+        .iter()
+        .position(|c| !b" \t\n\x0b\x0c\r".contains(c))
+        .unwrap_or(data.len());
+    let sign = if data.get(pos) == Some(&b'-') {
+        float.sign_bit()
+    } else {
+        0
+    };
+    if matches!(data.get(pos), Some(b'-' | b'+')) {
+        pos += 1;
+    }
+    let parsed = |bits: u64, end: usize, errno| Parsed {
+        value: float.to_return(bits | sign),
+        end: end as u64,
+        errno,
+    };
+    let matching = |word: &[u8]| {
+        word.iter()
+            .zip(&data[pos..])
+            .take_while(|(x, c)| **x == c.to_ascii_lowercase())
+            .count()
+    };
+
+    // `infinity`, or only `inf`
+    match matching(b"infinity") {
+        8 => return parsed(float.inf_bits(), pos + 8, Some(0)),
+        3.. => return parsed(float.inf_bits(), pos + 3, Some(0)),
+        1.. => return invalid,
+        0 => {}
+    }
+    // `nan`, with the optional `(chars)`
+    match matching(b"nan") {
+        3 => {
+            let end = pos + 3;
+            let chars = data[end..]
+                .iter()
+                .skip(1)
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_');
+            let close = end + 1 + chars.count();
+            let end = if data.get(end) == Some(&b'(')
+                && data.get(close) == Some(&b')')
+            {
+                close + 1
+            } else {
+                end
+            };
+            return Parsed {
+                value: float.to_return(float.inf_bits() | 1),
+                end: end as u64,
+                errno: Some(0),
+            };
+        }
+        1.. => return invalid,
+        0 => {}
+    }
+
+    let hex = data.get(pos) == Some(&b'0')
+        && matches!(data.get(pos + 1), Some(b'x' | b'X'));
+    let (digit_pos, radix) = if hex { (pos + 2, 16) } else { (pos, 10) };
+    let mut end = digit_pos;
+    let (mut dot, mut digits) = (false, 0);
+    let (mut mant, mut sticky, mut exp) = (0u128, false, 0i64);
+    loop {
+        match data.get(end) {
+            Some(b'.') if !dot => dot = true,
+            Some(c) if (*c as char).is_digit(radix) => {
+                let digit = (*c as char).to_digit(radix).unwrap();
+                digits += 1;
+                // only the hex mantissa is kept, with the extra digits in the
+                // sticky bit
+                if !hex {
+                    sticky |= digit != 0;
+                } else if mant >> 120 == 0 {
+                    mant = mant * 16 + u128::from(digit);
+                    exp -= if dot { 4 } else { 0 };
+                } else {
+                    sticky |= digit != 0;
+                    exp += if dot { 0 } else { 4 };
+                }
+            }
+            _ => break,
+        }
+        end += 1;
+    }
+    if digits == 0 {
+        // `0x` without digits is only the 0
+        return if hex {
+            parsed(0, pos + 1, Some(0))
+        } else {
+            invalid
+        };
+    }
+    let exp_char = if hex { b'p' } else { b'e' };
+    if data.get(end).map(u8::to_ascii_lowercase) == Some(exp_char) {
+        if let Some((value, exp_end)) = exponent(data, end) {
+            exp += value;
+            end = exp_end;
+        }
+    }
+
+    if !hex {
+        let text = std::str::from_utf8(&data[pos..end]).unwrap();
+        let bits = float.parse(text);
+        return parsed(bits, end, float.errno(bits, sticky));
+    }
+    if mant == 0 {
+        return parsed(0, end, Some(0));
+    }
+    let bits = float.round(mant, sticky, exp);
+    parsed(bits, end, float.errno(bits, true))
+}
+
+/// the exact decimal value of `mant * 2^exp`, multiplying by 5 is the same
+/// as dividing by 2 and shifting the point
+fn exact(mant: u64, exp: i32) -> String {
+    // little endian, base 10^9
+    let mut limbs = vec![mant % 1_000_000_000, mant / 1_000_000_000];
+    let factor = if exp < 0 { 5 } else { 2 };
+    for _ in 0..exp.unsigned_abs() {
+        let mut carry = 0;
+        for limb in &mut limbs {
+            let value = *limb * factor + carry;
+            *limb = value % 1_000_000_000;
+            carry = value / 1_000_000_000;
+        }
+        if carry != 0 {
+            limbs.push(carry);
+        }
+    }
+    let mut digits: String = limbs
+        .iter()
+        .rev()
+        .map(|limb| format!("{:09}", limb))
+        .collect();
+    let decimals = if exp < 0 {
+        exp.unsigned_abs() as usize
+    } else {
+        0
+    };
+    if digits.len() <= decimals {
+        digits.insert_str(0, &"0".repeat(decimals + 1 - digits.len()));
+    }
+    let (int, frac) = digits.split_at(digits.len() - decimals);
+    let int = int.trim_start_matches('0');
+    let int = if int.is_empty() { "0" } else { int };
+    if frac.is_empty() {
+        int.into()
+    } else {
+        format!("{}.{}", int, frac)
+    }
+}
+
+/// every halfway point, a bit above and a bit below it, and with a lot of
+/// zeros before the digit that decides the rounding
+fn tests_halfway() -> Vec<String> {
+    let mut tests = vec![];
+    for (mant, exp) in HALFWAY {
+        let tie = exact(*mant, *exp);
+        let point = if tie.contains('.') { "" } else { "." };
+        tests.push(format!("{}{}{}1", tie, point, "0".repeat(400)));
+        // the exact decimals of a fraction end with 5
+        if tie.contains('.') {
+            tests.push(tie[..tie.len() - 1].into());
+        }
+        tests.push(tie);
+    }
+    tests
+}
+
+pub struct TestStatic {
+    fn_sym: &'static str,
+    float: Float,
+    /// the string, with the NUL
+    data: Vec<u8>,
+}
+
+impl TestStatic {
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Parsed> {
+        let data_addr = Cell::new(0);
+        let endptr_addr = Cell::new(0);
+        let mut params = [
+            Param::heap_data_at(&self.data, &data_addr),
+            // the function writes the endptr here
+            Param::HeapFn(Box::new(|helper| {
+                let addr = helper.malloc(helper.ptr_size())?;
+                endptr_addr.set(addr);
+                Ok(addr)
+            })),
+        ];
+        let mut output = [self.float.to_return(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [value] = output;
+        let endptr = vm.helper_mut().read_ptr(endptr_addr.get())?;
+        Ok(Parsed {
+            value,
+            end: endptr.wrapping_sub(data_addr.get()),
+            errno: Some(vm.errno()?),
+        })
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let expected = strto(&self.data, self.float);
+        let output = self.call(fun_addr, ret_addr, vm)?;
+        let outcome = check_same(expected.value, output.value);
+        if !outcome.is_pass() {
+            return Ok(outcome);
+        }
+        let errno = expected.errno.or(output.errno);
+        Ok(check((expected.end, errno), (output.end, output.errno)))
+    }
+}
+
+/// a float printed with a random number of digits, so it's close to a
+/// halfway point most of the time, or random digits and hex floats
+fn random_input(rng: &mut Rng) -> Vec<u8> {
+    let mut data = match rng.below(4) {
+        0 => format!("{:e}", rng.f64()),
+        1 => format!("{:.*e}", 1 + rng.below(24) as usize, rng.f64()),
+        2 => {
+            let digits = rng.int_string();
+            let digits = String::from_utf8_lossy(c_str(&digits)).into_owned();
+            let exp = rng.below(700) as i64 - 350;
+            format!("{}.{}e{}", digits, rng.next_u64(), exp)
+        }
+        _ => {
+            let exp = rng.below(2400) as i64 - 1200;
+            format!("0x{:x}.{:x}p{}", rng.next_u64(), rng.next_u64(), exp)
+        }
+    }
+    .into_bytes();
+    data.push(0);
+    data
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    let tests_static = TESTS_STATIC
+        .iter()
+        .map(|data| data.to_string())
+        .chain(tests_halfway());
+    let tests_static: Vec<_> = tests_static.collect();
+
+    for (fn_sym, float) in [("strtod", Float::F64), ("strtof", Float::F32)] {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        for (i, data) in tests_static.iter().enumerate() {
+            let test = TestStatic {
+                fn_sym,
+                float,
+                data: format!("{}\x00", data).into_bytes(),
+            };
+            // the long ones are only shown in the failures
+            let name = if data.len() > 40 {
+                format!("static {}", i)
+            } else {
+                format!("static {} {:?}", i, data)
+            };
+            let passed = report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed && data.len() > 40 {
+                report.note(format_args!("input {:?}", data));
+            }
+        }
+
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let test = TestStatic {
+                fn_sym,
+                float,
+                data: random_input(&mut rng),
+            };
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(test.fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                let input = String::from_utf8_lossy(&test.data);
+                report.note(format_args!("input {:?}", input));
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+use icicle_mem::perm;
+
+/// return the offset and len of each token, like multiple strtok calls would
+fn tokens(data: &[u8], sep: &[u8]) -> Vec<(u64, u64)> {
+    let end = data.iter().position(|x| *x == 0).unwrap();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < end {
+        if sep.contains(&data[pos]) {
+            pos += 1;
+            continue;
+        }
+        let len = data[pos..end]
+            .iter()
+            .position(|x| sep.contains(x))
+            .unwrap_or(end - pos);
+        tokens.push((pos as u64, len as u64));
+        pos += len;
+    }
+    tokens
+}
+
+pub struct TestSession {
+    data: &'static [u8],
+    sep: &'static [u8],
+}
+
+impl TestSession {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // strtok keeps the position of the last token between calls
+        vm.begin_session();
+        let result = self.run(fun_addr, ret_addr, vm);
+        vm.end_session();
+        result
+    }
+
+    fn run(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // the sep param don't include the \x00
+        let sep = &self.sep[0..self.sep.len() - 1];
+        let data_addr = vm.helper_mut().malloc(self.data.len() as u64)?;
+        vm.helper_mut().icicle.cpu.mem.write_bytes(
+            data_addr,
+            self.data,
+            perm::NONE,
+        )?;
+
+        // the offset and content of each token, after the last token strtok
+        // returns NULL
+        let expected: Vec<_> = tokens(self.data, sep)
+            .into_iter()
+            .map(|(offset, len)| {
+                let start = offset as usize;
+                Some((offset, self.data[start..start + len as usize].to_vec()))
+            })
+            .chain([None])
+            .collect();
+        let mut actual = vec![];
+        let mut str_addr = data_addr;
+        for _ in 0..expected.len() {
+            let mut params =
+                [Param::Usize(str_addr), Param::HeapData(self.sep)];
+            let mut output = [Return::Usize(0)];
+            vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+            let [Return::Usize(output)] = output else { unreachable!() };
+            // only the first call receives the string
+            str_addr = 0;
+            if output == 0 {
+                actual.push(None);
+                continue;
+            }
+            let mut token = vec![];
+            vm.helper_mut()
+                .icicle
+                .cpu
+                .mem
+                .read_cstr(output, &mut token)?;
+            actual.push(Some((output.wrapping_sub(data_addr), token)));
+        }
+        Ok(check(expected, actual))
+    }
+}
+
+pub const TESTS_SESSION: [(&[u8], &[u8]); 7] = [
+    (b"test\x00", b" \x00"),
+    (b"\x00", b" \x00"),
+    (b"   \x00", b" \x00"),
+    (b"a b c\x00", b" \x00"),
+    (b"  hello,world;;foo \x00", b" ,;\x00"),
+    (b"\xff\x01\xfe\xff\x80\x00\xff", b"\xff\x00"),
+    (b"no separator here\x00", b"\x00"),
+];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "strtok";
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    let tests_session = TESTS_SESSION
+        .into_iter()
+        .map(|(data, sep)| TestSession { data, sep });
+    for (i, test) in tests_session.enumerate() {
+        report.run(FN_SYM, format!("session {}", i), vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+}
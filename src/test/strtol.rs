@@ -0,0 +1,326 @@
+//! The `strtol` family, with every base, the endptr and the errno. `long` is
+//! 32 bits on i486/i686, so the same inputs saturate in different places.
+
+use std::cell::Cell;
+
+use crate::random::{self, Rng};
+use crate::report::{check, Outcome, Report};
+use crate::test::common::{EINVAL, ERANGE};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+/// the function, if it's signed and the bits of the result, None for `long`
+const FUNCTIONS: &[(&str, bool, Option<u32>)] = &[
+    ("strtol", true, None),
+    ("strtoul", false, None),
+    ("strtoll", true, Some(64)),
+    ("strtoull", false, Some(64)),
+    ("strtoimax", true, Some(64)),
+    ("strtoumax", false, Some(64)),
+];
+
+pub const TESTS_STATIC: &[(&str, u32)] = &[
+    ("0", 10),
+    ("123", 10),
+    (" \t\n\x0b\x0c\r-42", 10),
+    ("+7", 10),
+    ("+-1", 10),
+    ("-", 10),
+    ("", 10),
+    ("abc", 10),
+    ("12abc", 10),
+    ("12abc", 16),
+    (" 12 34", 10),
+    ("0", 0),
+    ("017", 0),
+    ("09", 0),
+    ("0x1f", 0),
+    ("0X1F", 16),
+    ("-0x1f", 0),
+    ("0x", 16),
+    ("0xg", 0),
+    ("0x1g", 16),
+    ("0x1f", 10),
+    ("101", 2),
+    ("777", 8),
+    ("12", 3),
+    ("zZ", 36),
+    ("1", 1),
+    ("1", 37),
+    ("2147483647", 10),
+    ("2147483648", 10),
+    ("-2147483648", 10),
+    ("-2147483649", 10),
+    ("4294967295", 10),
+    ("4294967296", 10),
+    ("-1", 10),
+    ("-4294967296", 10),
+    ("9223372036854775807", 10),
+    ("9223372036854775808", 10),
+    ("-9223372036854775808", 10),
+    ("-9223372036854775809", 10),
+    ("18446744073709551615", 10),
+    ("18446744073709551616", 10),
+    ("-18446744073709551616", 10),
+    ("99999999999999999999999999999999999999999999", 10),
+    ("0x7fffffffffffffff", 0),
+    ("0xffffffffffffffff", 0),
+    ("0x10000000000000000", 0),
+];
+
+/// what the function returns, with the endptr as an offset into the string
+#[derive(Debug, PartialEq)]
+struct Parsed {
+    value: u64,
+    end: u64,
+    errno: i32,
+}
+
+/// the musl implementation, the value is saturated on overflow
+fn strto(data: &[u8], base: u32, signed: bool, bits: u32) -> Parsed {
+    let invalid = Parsed {
+        value: 0,
+        end: 0,
+        errno: EINVAL,
+    };
+    if base == 1 || base > 36 {
+        return invalid;
+    }
+    let digit =
+        |pos: usize| data.get(pos).and_then(|c| (*c as char).to_digit(36));
+    let mut pos = data
+        .iter()
+        .position(|c| !b" \t\n\x0b\x0c\r".contains(c))
+        .unwrap_or(data.len());
+    let neg = data.get(pos) == Some(&b'-');
+    if matches!(data.get(pos), Some(b'-' | b'+')) {
+        pos += 1;
+    }
+    let mut base = base;
+    if (base == 0 || base == 16) && data.get(pos) == Some(&b'0') {
+        if matches!(data.get(pos + 1), Some(b'x' | b'X')) {
+            // without a hex digit after it, only the 0 is parsed
+            if !digit(pos + 2).is_some_and(|digit| digit < 16) {
+                let end = pos as u64 + 1;
+                return Parsed {
+                    value: 0,
+                    end,
+                    errno: 0,
+                };
+            }
+            pos += 2;
+            base = 16;
+        } else if base == 0 {
+            base = 8;
+        }
+    } else {
+        if base == 0 {
+            base = 10;
+        }
+        // nothing is parsed, and the endptr is the start of the string,
+        // `__intscan` also sets EINVAL
+        if !digit(pos).is_some_and(|digit| digit < base) {
+            return invalid;
+        }
+    }
+
+    let mut value: u128 = 0;
+    while let Some(digit) = digit(pos).filter(|digit| *digit < base) {
+        value = value
+            .saturating_mul(base.into())
+            .saturating_add(digit.into());
+        pos += 1;
+    }
+    // the min for signed, without the sign, and the max for unsigned
+    let lim: u128 = if signed {
+        1 << (bits - 1)
+    } else {
+        (1 << bits) - 1
+    };
+    let (value, errno) = if signed && !neg && value >= lim {
+        (lim - 1, ERANGE)
+    } else if value > lim {
+        (lim, ERANGE)
+    } else if neg {
+        // also for unsigned, `-1` is the max
+        (value.wrapping_neg(), 0)
+    } else {
+        (value, 0)
+    };
+    Parsed {
+        value: value as u64 & (u64::MAX >> (64 - bits)),
+        end: pos as u64,
+        errno,
+    }
+}
+
+pub struct TestStatic {
+    fn_sym: &'static str,
+    signed: bool,
+    /// None for `long`, that have the size of a pointer
+    bits: Option<u32>,
+    /// the string, with the NUL
+    data: Vec<u8>,
+    base: u32,
+}
+
+impl TestStatic {
+    fn call(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+        bits: u32,
+    ) -> Result<Parsed> {
+        let data_addr = Cell::new(0);
+        let endptr_addr = Cell::new(0);
+        let mut params = [
+            Param::heap_data_at(&self.data, &data_addr),
+            // the function writes the endptr here
+            Param::HeapFn(Box::new(|helper| {
+                let addr = helper.malloc(helper.ptr_size())?;
+                endptr_addr.set(addr);
+                Ok(addr)
+            })),
+            Param::Usize(self.base.into()),
+        ];
+        let mut output = match self.bits {
+            None => [Return::Usize(0)],
+            Some(_) => [Return::I64(0)],
+        };
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let value = match output {
+            [Return::Usize(value)] => value,
+            [Return::I64(value)] => value as u64,
+            _ => unreachable!(),
+        };
+        let endptr = vm.helper_mut().read_ptr(endptr_addr.get())?;
+        Ok(Parsed {
+            value: value & (u64::MAX >> (64 - bits)),
+            end: endptr.wrapping_sub(data_addr.get()),
+            errno: vm.errno()?,
+        })
+    }
+
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        // long have the size of a pointer
+        let bits = self.bits.unwrap_or(vm.helper().ptr_size() as u32 * 8);
+        let expected = strto(&self.data, self.base, self.signed, bits);
+        let output = self.call(fun_addr, ret_addr, vm, bits)?;
+        Ok(check(expected, output))
+    }
+}
+
+/// the value written in the base
+fn to_radix(mut value: u128, base: u32) -> String {
+    let mut digits = vec![];
+    loop {
+        let digit = (value % u128::from(base)) as u32;
+        digits.push(char::from_digit(digit, base).unwrap());
+        value /= u128::from(base);
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
+/// for every base, the max values and the ones after it, that overflow, and
+/// a digit that is not valid in the base
+fn tests_bases() -> Vec<(String, u32)> {
+    let mut tests = vec![];
+    for base in 2..=36 {
+        for max in [u128::from(u32::MAX), u128::from(u64::MAX)] {
+            tests.push((to_radix(max, base), base));
+            tests.push((to_radix(max + 1, base), base));
+            tests.push((format!("-{}", to_radix(max, base)), base));
+        }
+        if let Some(invalid) = char::from_digit(base, 36) {
+            tests.push((format!("1{}", invalid), base));
+        }
+    }
+    tests
+}
+
+/// a number in a random base, or a decimal one with spaces and garbage
+fn random_input(rng: &mut Rng) -> (Vec<u8>, u32) {
+    match rng.below(3) {
+        0 => (rng.int_string(), *rng.choose(&[0, 10])),
+        _ => {
+            let base = match rng.below(4) {
+                0 => 0,
+                1 => 16,
+                _ => 2 + rng.below(35) as u32,
+            };
+            let mut data = vec![];
+            match rng.below(4) {
+                0 => data.push(b'-'),
+                1 => data.extend(b"0x"),
+                _ => {}
+            }
+            // sometimes a digit that is not valid in the base, and ends it
+            let max = if base == 0 { 10 } else { base };
+            for _ in 0..rng.below(24) {
+                let digit = rng.below(u64::from(max) + 1).min(35) as u32;
+                data.push(char::from_digit(digit, 36).unwrap() as u8);
+            }
+            data.push(0);
+            (data, base)
+        }
+    }
+}
+
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+    let tests_static = TESTS_STATIC
+        .iter()
+        .map(|(data, base)| (data.to_string(), *base))
+        .chain(tests_bases());
+    let tests_static: Vec<_> = tests_static.collect();
+
+    for &(fn_sym, signed, bits) in FUNCTIONS {
+        let Some(fun_addr) = report.lookup(vm, fn_sym) else {
+            continue;
+        };
+        for (i, (data, base)) in tests_static.iter().enumerate() {
+            let test = TestStatic {
+                fn_sym,
+                signed,
+                bits,
+                data: format!("{}\x00", data).into_bytes(),
+                base: *base,
+            };
+            let name = format!("static {} base {} {:?}", i, base, data);
+            report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+        }
+
+        let mut rng = Rng::for_fn(fn_sym);
+        for i in 0..random::cases() {
+            let (data, base) = random_input(&mut rng);
+            let test = TestStatic {
+                fn_sym,
+                signed,
+                bits,
+                data,
+                base,
+            };
+            let name = format!("random {} seed 0x{:016x}", i, random::seed());
+            let passed = report.run(fn_sym, name, vm, |vm| {
+                test.test_on_vm(fun_addr, ret_addr, vm)
+            });
+            if !passed {
+                let input = String::from_utf8_lossy(&test.data);
+                report.note(format_args!("input {:?} base {}", input, base));
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::report::{check, Outcome, Report};
+use crate::vm::{Param, Return, Vm};
+use anyhow::Result;
+
+pub struct TestStatic {
+    name: u64,
+    result: u64,
+}
+
+impl TestStatic {
+    fn test_on_vm(
+        &self,
+        fun_addr: u64,
+        ret_addr: u64,
+        vm: &mut impl Vm,
+    ) -> Result<Outcome> {
+        let mut params = [Param::Usize(self.name)];
+        let mut output = [Return::Usize(0)];
+        vm.call(fun_addr, ret_addr, &mut params, &mut output)?;
+        let [Return::Usize(output)] = output else { unreachable!() };
+        // sysconf returns a long
+        let mask = u64::MAX >> (64 - (vm.helper().ptr_size() * 8));
+        Ok(check(self.result & mask, output & mask))
+    }
+}
+
+/// the name and the value after boot, only the page size may come from the
+/// auxv, the others are constants of musl
+pub const TESTS_STATIC: &[(u64, u64)] = &[
+    // _SC_PAGESIZE, from AT_PAGESZ on aarch64, a constant on x86
+    (30, 0x1000),
+    // _SC_CLK_TCK, always 100
+    (2, 100),
+    // _SC_NGROUPS_MAX
+    (3, 32),
+    // invalid name, returns -1
+    (0xffff, u64::MAX),
+];
+pub fn all_tests(vm: &mut impl Vm, report: &mut Report) {
+    const FN_SYM: &str = "sysconf";
+    // the page size of aarch64 is only set after musl is initialized
+    if vm.helper().boot.is_none() {
+        return;
+    }
+    let Some(fun_addr) = report.lookup(vm, FN_SYM) else {
+        return;
+    };
+    let Some(ret_addr) = report.lookup(vm, "_dlstart") else {
+        return;
+    };
+
+    let tests_static = TESTS_STATIC.iter().map(|(name, result)| TestStatic {
+        name: *name,
+        result: *result,
+    });
+    for (i, test) in tests_static.enumerate() {
+        let name = format!("static {} name({})", i, test.name);
+        report.run(FN_SYM, name, vm, |vm| {
+            test.test_on_vm(fun_addr, ret_addr, vm)
+        });
+    }
+}
@@ -1,7 +1,14 @@
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
 use anyhow::{bail, Result};
 use icicle_mem::perm;
+use pcode::VarNode;
 
+use crate::elf::{Elf, DT_INIT, DT_INIT_ARRAY, DT_INIT_ARRAYSZ};
 use crate::helper;
+use crate::syscall::FakeKernel;
 
 pub enum Param<'a, 'b> {
     /// this usize is the param
@@ -18,6 +25,62 @@ pub enum Param<'a, 'b> {
     HeapFn(Box<dyn FnMut(&mut IcicleHelper) -> Result<u64> + 'b>),
 }
 
+impl<'a, 'b> Param<'a, 'b> {
+    /// same as [`Param::HeapData`], but the addr of the data is saved, so a
+    /// returned pointer can be decoded with [`ptr_offset`]
+    pub fn heap_data_at(data: &'b [u8], addr: &'b Cell<u64>) -> Self {
+        Param::HeapFn(Box::new(move |helper| {
+            let data_addr = helper.malloc(data.len() as u64)?;
+            helper
+                .icicle
+                .cpu
+                .mem
+                .write_bytes(data_addr, data, perm::NONE)?;
+            addr.set(data_addr);
+            Ok(data_addr)
+        }))
+    }
+}
+
+impl Param<'_, '_> {
+    /// the floats are passed in their own registers, on the archs that have
+    /// them
+    pub fn is_float(&self) -> bool {
+        matches!(self, Param::F32(_) | Param::F64(_))
+    }
+}
+
+/// a returned pointer, as an offset into the data at `base`, None if it's
+/// NULL
+pub fn ptr_offset(ptr: u64, base: u64) -> Option<u64> {
+    (ptr != 0).then(|| ptr.wrapping_sub(base))
+}
+
+/// an owned param, that can be used to create the same [`Param`] multiple
+/// times, eg: to call the same function on multiple vms
+#[derive(Clone, Debug)]
+pub enum Input {
+    Usize(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    /// put the data to the heap and add a pointer as a param
+    Bytes(Vec<u8>),
+}
+
+impl Input {
+    pub fn param(&self) -> Param {
+        match self {
+            Input::Usize(value) => Param::Usize(*value),
+            Input::I64(value) => Param::I64(*value),
+            Input::F32(value) => Param::F32(*value),
+            Input::F64(value) => Param::F64(*value),
+            Input::Bytes(data) => Param::HeapData(data),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Return {
     /// Is a simple usize value
     Usize(u64),
@@ -31,15 +94,78 @@ pub enum Return {
     CString(Vec<u8>),
 }
 
+impl Return {
+    /// compare the values bitwise, also the sign and payload of the NaNs
+    pub fn same_bits(&self, other: &Return) -> bool {
+        match (self, other) {
+            (Return::Usize(x), Return::Usize(y)) => x == y,
+            (Return::I64(x), Return::I64(y)) => x == y,
+            (Return::F32(x), Return::F32(y)) => x.to_bits() == y.to_bits(),
+            (Return::F64(x), Return::F64(y)) => x.to_bits() == y.to_bits(),
+            (Return::CString(x), Return::CString(y)) => x == y,
+            _ => false,
+        }
+    }
+
+    /// same as [`Return::same_bits`], but any NaN is equal to any other NaN
+    pub fn same_as(&self, other: &Return) -> bool {
+        match (self, other) {
+            (Return::F32(x), Return::F32(y)) if x.is_nan() && y.is_nan() => {
+                true
+            }
+            (Return::F64(x), Return::F64(y)) if x.is_nan() && y.is_nan() => {
+                true
+            }
+            _ => self.same_bits(other),
+        }
+    }
+
+    /// the number of floats between the values, both zeros are at distance
+    /// 0. None if they are not finite floats of the same type
+    pub fn ulps(&self, other: &Return) -> Option<u64> {
+        let (x, y, sign) = match (self, other) {
+            (Return::F32(x), Return::F32(y))
+                if x.is_finite() && y.is_finite() =>
+            {
+                (u64::from(x.to_bits()), u64::from(y.to_bits()), 1 << 31)
+            }
+            (Return::F64(x), Return::F64(y))
+                if x.is_finite() && y.is_finite() =>
+            {
+                (x.to_bits(), y.to_bits(), 1 << 63)
+            }
+            _ => return None,
+        };
+        // the bits of a float increase with the value, only the sign is
+        // apart
+        let ordered = |bits: u64| match bits & sign {
+            0 => i128::from(bits),
+            _ => -i128::from(bits & !sign),
+        };
+        Some((ordered(x) - ordered(y)).unsigned_abs() as u64)
+    }
+}
+
+/// what the emulator did on the calls made since the last
+/// [`Vm::take_stats`]
+#[derive(Clone, Debug, Default)]
+pub struct CallStats {
+    pub calls: u64,
+    pub instructions: u64,
+    /// why the emulator stopped on the last call, eg: `Breakpoint`
+    pub exit: Option<String>,
+    /// the results of every call, used by the golden snapshots
+    pub results: Vec<Return>,
+}
+
 pub trait Vm {
     fn helper(&self) -> &IcicleHelper;
     fn helper_mut(&mut self) -> &mut IcicleHelper;
-    fn lookup_symbol(&mut self, function_sym: &'static str) -> u64 {
-        self.helper_mut()
-            .icicle
-            .env
-            .lookup_symbol(function_sym)
-            .unwrap()
+    fn lookup_symbol(&mut self, function_sym: &'static str) -> Result<u64> {
+        match self.helper_mut().icicle.env.lookup_symbol(function_sym) {
+            Some(addr) => Ok(addr),
+            None => bail!("symbol {} not found", function_sym),
+        }
     }
     fn call(
         &mut self,
@@ -48,10 +174,152 @@ pub trait Vm {
         params: &mut [Param],
         results: &mut [Return],
     ) -> Result<()>;
+    /// calls until [`Vm::end_session`] share the guest state, this allow
+    /// testing functions with hidden state, like `strtok` and `rand`
+    fn begin_session(&mut self) {
+        self.helper_mut().begin_session()
+    }
+    fn end_session(&mut self) {
+        self.helper_mut().end_session()
+    }
+    /// the data written by the guest into stdout, since the last restore
+    fn stdout(&self) -> Vec<u8> {
+        self.helper().output(1)
+    }
+    /// the data written by the guest into stderr, since the last restore
+    fn stderr(&self) -> Vec<u8> {
+        self.helper().output(2)
+    }
+    /// read the errno left by the last call
+    fn errno(&mut self) -> Result<i32> {
+        let fun_addr = self.lookup_symbol("__errno_location")?;
+        let ret_addr = self.lookup_symbol("_dlstart")?;
+        // don't restore the snapshot, that would clean the errno
+        let session = std::mem::replace(&mut self.helper_mut().session, true);
+        let mut output = [Return::Usize(0)];
+        let result = self.call(fun_addr, ret_addr, &mut [], &mut output);
+        self.helper_mut().session = session;
+        result?;
+        let [Return::Usize(addr)] = output else { unreachable!() };
+        // errno is an int
+        Ok(self.helper_mut().read_uint(addr, 4)? as i32)
+    }
+    /// the stats of the calls since the last time this was called
+    fn take_stats(&mut self) -> CallStats {
+        std::mem::take(&mut self.helper_mut().stats)
+    }
+    /// run the musl initialization, like the start of a process would, and
+    /// make the result the new snapshot, so functions that depend on the
+    /// process state, like `getenv` and `sysconf`, can be tested
+    fn boot(&mut self, boot: &Boot) -> Result<()> {
+        // `__init_libc` is LOCAL, only in the `.symtab`
+        let elf = Elf::read(&self.helper().musl)?;
+        let init_libc = self.helper_mut().elf_symbol(&elf, "__init_libc")?;
+        let ret_addr = self.lookup_symbol("_dlstart")?;
+
+        self.begin_session();
+        let result = (|| {
+            let (argv, envp) = self.helper_mut().write_process_stack(boot)?;
+            let argv0 = self.helper_mut().read_ptr(argv)?;
+            let mut params = [Param::Usize(envp), Param::Usize(argv0)];
+            self.call(init_libc, ret_addr, &mut params, &mut [])?;
+            // the `__libc_start_init` of the shared library is the one of the
+            // dynamic linker, it walks the ctor queue created by `__dls3`,
+            // that is NULL here, so do what it does for this library
+            for init in self.helper_mut().init_functions(&elf)? {
+                self.call(init, ret_addr, &mut [], &mut [])?;
+            }
+            Ok(())
+        })();
+        self.end_session();
+        result?;
+
+        let helper = self.helper_mut();
+        helper.take_snapshot();
+        helper.boot = Some(boot.clone());
+        Ok(())
+    }
+}
+
+/// allow boxed backends, like the ones created for every arch, to be used as
+/// a [`Vm`]
+impl<T: Vm + ?Sized> Vm for Box<T> {
+    fn helper(&self) -> &IcicleHelper {
+        (**self).helper()
+    }
+    fn helper_mut(&mut self) -> &mut IcicleHelper {
+        (**self).helper_mut()
+    }
+    fn lookup_symbol(&mut self, function_sym: &'static str) -> Result<u64> {
+        (**self).lookup_symbol(function_sym)
+    }
+    fn call(
+        &mut self,
+        function_addr: u64,
+        return_addr: u64,
+        params: &mut [Param],
+        results: &mut [Return],
+    ) -> Result<()> {
+        (**self).call(function_addr, return_addr, params, results)
+    }
+    fn begin_session(&mut self) {
+        (**self).begin_session()
+    }
+    fn end_session(&mut self) {
+        (**self).end_session()
+    }
+    fn stdout(&self) -> Vec<u8> {
+        (**self).stdout()
+    }
+    fn stderr(&self) -> Vec<u8> {
+        (**self).stderr()
+    }
+    fn errno(&mut self) -> Result<i32> {
+        (**self).errno()
+    }
+    fn take_stats(&mut self) -> CallStats {
+        (**self).take_stats()
+    }
+    fn boot(&mut self, boot: &Boot) -> Result<()> {
+        (**self).boot(boot)
+    }
+}
+
+/// the process arguments and environment used to initialize musl
+#[derive(Clone)]
+pub struct Boot {
+    pub args: Vec<Vec<u8>>,
+    pub env: Vec<Vec<u8>>,
+}
+
+impl Default for Boot {
+    fn default() -> Self {
+        Self {
+            args: vec![b"icicle-pingu".to_vec()],
+            env: vec![
+                b"HOME=/root".to_vec(),
+                b"PATH=/usr/bin:/bin".to_vec(),
+                b"PINGU=icicle-pingu".to_vec(),
+                b"EMPTY=".to_vec(),
+            ],
+        }
+    }
+}
+
+/// how the arch place the TLS block in relation to the thread pointer
+pub enum TlsVariant {
+    /// the TLS is bellow the thread pointer, that points to the
+    /// `struct pthread`, used by x86 and x86_64
+    BelowTp,
+    /// the TLS is above the thread pointer, after `gap` bytes, with the
+    /// `struct pthread` bellow it, used by aarch64 and riscv
+    AboveTp { gap: u64 },
 }
 
 pub struct IcicleHelper {
     pub icicle: icicle_vm::Vm,
+    /// the library loaded into the vm
+    pub musl: PathBuf,
     pub stack_addr: u64,
     pub stack_size: u64,
     pub stack_max: u64,
@@ -59,18 +327,44 @@ pub struct IcicleHelper {
     pub heap_used: u64,
     pub heap_size: u64,
     pub heap_max: u64,
+    snapshot: Option<HelperSnapshot>,
+    pub session: bool,
+    /// the process state musl was initialized with, if any
+    pub boot: Option<Boot>,
+    /// answer the syscalls made by the guest
+    pub kernel: Rc<RefCell<FakeKernel>>,
+    pub stats: CallStats,
+}
+
+/// space reserved for the musl `struct pthread`, it's way bigger then needed
+const PTHREAD_LEN: u64 = 0x1000;
+/// the TLS block is placed after the stack and heap
+const TLS_ADDR: u64 = 0x3000_0000;
+/// the argv, envp and auxv are placed after the TLS block
+const PROCESS_STACK_ADDR: u64 = 0x3100_0000;
+/// the AT_RANDOM bytes, used for the stack canary
+const AT_RANDOM_BYTES: [u8; 16] = *b"icicle-pingu-rnd";
+
+/// the vm state saved after the library is loaded, restored before each call
+struct HelperSnapshot {
+    vm: icicle_vm::Snapshot,
+    stack_size: u64,
+    heap_size: u64,
 }
 
 impl IcicleHelper {
     pub fn new(
         icicle: icicle_vm::Vm,
+        musl: &Path,
         stack_addr: u64,
         stack_max: u64,
         heap_addr: u64,
         heap_max: u64,
+        kernel: Rc<RefCell<FakeKernel>>,
     ) -> Self {
         Self {
             icicle,
+            musl: musl.to_path_buf(),
             stack_addr,
             stack_size: 0,
             stack_max,
@@ -78,9 +372,260 @@ impl IcicleHelper {
             heap_used: 0,
             heap_size: 0,
             heap_max,
+            snapshot: None,
+            session: false,
+            boot: None,
+            kernel,
+            stats: CallStats::default(),
+        }
+    }
+
+    /// run until the return addr, keeping the [`CallStats`]
+    pub fn run_until(&mut self, return_addr: u64) -> icicle_vm::VmExit {
+        let icount = self.icicle.cpu.icount;
+        let vm_exit = self.icicle.run_until(return_addr);
+        self.stats.calls += 1;
+        self.stats.instructions += self.icicle.cpu.icount.wrapping_sub(icount);
+        self.stats.exit = Some(format!("{:?}", vm_exit));
+        vm_exit
+    }
+
+    /// save the current cpu and memory state, future calls to
+    /// [`IcicleHelper::restore`] will return to it
+    pub fn take_snapshot(&mut self) {
+        self.snapshot = Some(HelperSnapshot {
+            vm: self.icicle.snapshot(),
+            stack_size: self.stack_size,
+            heap_size: self.heap_size,
+        });
+    }
+
+    /// restore the cpu and memory to the last snapshot and free all the heap,
+    /// so nothing written by a previous call leaks into the next one
+    pub fn restore(&mut self) {
+        let snapshot = self
+            .snapshot
+            .as_ref()
+            .expect("restore called before take_snapshot");
+        self.icicle.restore(&snapshot.vm);
+        // the stack and heap mapped after the snapshot are gone
+        self.stack_size = snapshot.stack_size;
+        self.heap_size = snapshot.heap_size;
+        self.free_all();
+        self.kernel.borrow_mut().clear();
+    }
+
+    /// start from a clean state and keep the guest state between calls, until
+    /// [`IcicleHelper::end_session`] is called
+    pub fn begin_session(&mut self) {
+        self.restore();
+        self.session = true;
+    }
+
+    /// the next call will restore the snapshot again
+    pub fn end_session(&mut self) {
+        self.session = false;
+    }
+
+    /// prepare the vm for a new call, inside a session the previous state,
+    /// including the heap, is kept
+    pub fn prepare_call(&mut self) {
+        if !self.session {
+            self.restore();
         }
     }
 
+    /// the data written into `fd`, captured by the fake kernel
+    pub fn output(&self, fd: u64) -> Vec<u8> {
+        let kernel = self.kernel.borrow();
+        kernel.fds.get(&fd).cloned().unwrap_or_default()
+    }
+
+    /// size of a pointer in the guest
+    pub fn ptr_size(&self) -> u64 {
+        helper::ptr_size(&self.icicle.cpu)
+    }
+
+    /// read an unsigned int with `len` bytes, in the guest endianess
+    pub fn read_uint(&mut self, addr: u64, len: u64) -> Result<u64> {
+        helper::read_uint(&mut self.icicle.cpu, addr, len)
+    }
+
+    /// write an unsigned int with `len` bytes, in the guest endianess
+    pub fn write_uint(
+        &mut self,
+        addr: u64,
+        value: u64,
+        len: u64,
+    ) -> Result<()> {
+        helper::write_uint(&mut self.icicle.cpu, addr, value, len)
+    }
+
+    pub fn write_ptr(&mut self, addr: u64, value: u64) -> Result<()> {
+        self.write_uint(addr, value, self.ptr_size())
+    }
+
+    pub fn read_ptr(&mut self, addr: u64) -> Result<u64> {
+        self.read_uint(addr, self.ptr_size())
+    }
+
+    /// the addr of a symbol of the loaded library, also the LOCAL ones that
+    /// are only in the `.symtab`
+    pub fn elf_symbol(&mut self, elf: &Elf, name: &str) -> Result<u64> {
+        let base = self.load_base(elf)?;
+        match elf.symbol(name)? {
+            Some(value) => Ok(base.wrapping_add(value)),
+            None => bail!("symbol {} not found", name),
+        }
+    }
+
+    /// the addr the library was loaded at
+    fn load_base(&mut self, elf: &Elf) -> Result<u64> {
+        // `_dlstart` is always exported
+        match (
+            self.icicle.env.lookup_symbol("_dlstart"),
+            elf.symbol("_dlstart")?,
+        ) {
+            (Some(addr), Some(value)) => Ok(addr.wrapping_sub(value)),
+            _ => bail!("_dlstart not found"),
+        }
+    }
+
+    /// the DT_INIT and the DT_INIT_ARRAY functions of the library, in the
+    /// order the dynamic linker calls them
+    pub fn init_functions(&mut self, elf: &Elf) -> Result<Vec<u64>> {
+        let base = self.load_base(elf)?;
+        let ptr_size = self.ptr_size();
+        let mut functions = vec![];
+        if let Some(init) = elf.dynamic(DT_INIT)?.filter(|init| *init != 0) {
+            functions.push(base.wrapping_add(init));
+        }
+        if let Some(array) = elf.dynamic(DT_INIT_ARRAY)? {
+            let len = elf.dynamic(DT_INIT_ARRAYSZ)?.unwrap_or(0) / ptr_size;
+            let array = base.wrapping_add(array);
+            for i in 0..len {
+                // the entries were already relocated by the loader
+                functions.push(self.read_ptr(array + (i * ptr_size))?);
+            }
+        }
+        Ok(functions)
+    }
+
+    /// write the argc, argv, envp and auxv, like the kernel does at the start
+    /// of the process stack, returns the addr of argv and envp
+    pub fn write_process_stack(&mut self, boot: &Boot) -> Result<(u64, u64)> {
+        const AT_NULL: u64 = 0;
+        const AT_PAGESZ: u64 = 6;
+        const AT_UID: u64 = 11;
+        const AT_EUID: u64 = 12;
+        const AT_GID: u64 = 13;
+        const AT_EGID: u64 = 14;
+        const AT_HWCAP: u64 = 16;
+        const AT_SECURE: u64 = 23;
+        const AT_RANDOM: u64 = 25;
+
+        let ptr_size = self.ptr_size();
+        // argc, argv + NULL, envp + NULL and 9 auxv pairs
+        let table_len = 1 + (boot.args.len() + 1) + (boot.env.len() + 1) + 18;
+        let table_len = table_len as u64 * ptr_size;
+        let strings_len: usize = boot
+            .args
+            .iter()
+            .chain(boot.env.iter())
+            .map(|string| string.len() + 1)
+            .sum();
+        let len = table_len + AT_RANDOM_BYTES.len() as u64 + strings_len as u64;
+        let (addr, _len) = helper::create_empty_memory(
+            &mut self.icicle.cpu.mem,
+            Some(PROCESS_STACK_ADDR),
+            len,
+            perm::READ | perm::WRITE,
+        )?;
+
+        let random = addr + table_len;
+        self.icicle.cpu.mem.write_bytes(
+            random,
+            &AT_RANDOM_BYTES,
+            perm::NONE,
+        )?;
+        // write the strings after the table, the memory is already zeroed
+        let mut string_pos = random + AT_RANDOM_BYTES.len() as u64;
+        let mut write_strings = |helper: &mut Self, strings: &[Vec<u8>]| {
+            let mut ptrs = vec![];
+            for string in strings {
+                helper.icicle.cpu.mem.write_bytes(
+                    string_pos,
+                    string,
+                    perm::NONE,
+                )?;
+                ptrs.push(string_pos);
+                string_pos += string.len() as u64 + 1;
+            }
+            // NULL terminate the array
+            ptrs.push(0);
+            Ok::<_, anyhow::Error>(ptrs)
+        };
+        let args = write_strings(self, &boot.args)?;
+        let env = write_strings(self, &boot.env)?;
+        let auxv = [
+            AT_PAGESZ, 0x1000, AT_UID, 0, AT_EUID, 0, AT_GID, 0, AT_EGID, 0,
+            AT_HWCAP, 0, AT_SECURE, 0, AT_RANDOM, random, AT_NULL, 0,
+        ];
+
+        let argc = boot.args.len() as u64;
+        let argv = addr + ptr_size;
+        let envp = argv + (args.len() as u64 * ptr_size);
+        let table = [argc].into_iter().chain(args).chain(env).chain(auxv);
+        for (i, value) in table.enumerate() {
+            self.write_ptr(addr + (i as u64 * ptr_size), value)?;
+        }
+        Ok((argv, envp))
+    }
+
+    /// create the TLS block and the `struct pthread` for the main thread,
+    /// using the PT_TLS from the library, and set the thread pointer, so
+    /// functions like `__errno_location` work
+    pub fn init_tls(
+        &mut self,
+        tp_reg: VarNode,
+        variant: TlsVariant,
+    ) -> Result<u64> {
+        let elf = Elf::read(&self.musl)?;
+        let (image, tls_len, tls_align) = match elf.tls() {
+            Some(tls) => (elf.segment_data(tls)?, tls.memsz, tls.align.max(1)),
+            None => (&[][..], 0, 1),
+        };
+        let tls_len = (tls_len + (tls_align - 1)) & !(tls_align - 1);
+        let gap = match variant {
+            TlsVariant::BelowTp => 0,
+            TlsVariant::AboveTp { gap } => gap,
+        };
+        let (block, _len) = helper::create_empty_memory(
+            &mut self.icicle.cpu.mem,
+            Some(TLS_ADDR),
+            PTHREAD_LEN + gap + tls_len + PTHREAD_LEN,
+            perm::READ | perm::WRITE,
+        )?;
+        let (tp, tls_addr) = match variant {
+            TlsVariant::BelowTp => {
+                let tp = block + PTHREAD_LEN + tls_len;
+                // x86 read the `struct pthread` self pointer from tp
+                self.write_ptr(tp, tp)?;
+                (tp, tp - tls_len)
+            }
+            TlsVariant::AboveTp { gap } => {
+                let tp = block + PTHREAD_LEN;
+                (tp, tp + gap)
+            }
+        };
+        self.icicle
+            .cpu
+            .mem
+            .write_bytes(tls_addr, image, perm::NONE)?;
+        self.icicle.cpu.write_reg(tp_reg, tp);
+        Ok(tp)
+    }
+
     /// add this data to the stack
     pub fn set_stack_len(&mut self, len: u64) -> Result<()> {
         if len > self.stack_max {